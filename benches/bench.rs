@@ -18,6 +18,13 @@ fn nest(n: usize, f: &impl Fn()) {
 }
 
 fn bench_sjlj(c: &mut Criterion) {
+    // Run with and without `--features frame-walk` to compare both paths.
+    let sjlj = if cfg!(feature = "frame-walk") {
+        "sjlj-frame-walk"
+    } else {
+        "sjlj"
+    };
+
     c.bench_function(&format!("nest{NEST_LVL}"), |b| {
         let capture = 42;
         b.iter(|| {
@@ -32,7 +39,7 @@ fn bench_sjlj(c: &mut Criterion) {
         ("jump0", true, 0, 14),
         (&format!("jump{NEST_LVL}"), true, NEST_LVL, 14),
     ] {
-        c.bench_function(&format!("sjlj/{name}"), |b| {
+        c.bench_function(&format!("{sjlj}/{name}"), |b| {
             let jump = black_box(jump);
             let lvl = black_box(lvl);
             b.iter(|| {
@@ -6,4 +6,53 @@ fn main() {
     if emit_cfi {
         println!("cargo::rustc-cfg=emit_cfi");
     }
+
+    // Use the portable implementation only if there is no native one, or under Miri which does
+    // not support inline assembly.
+    println!("cargo::rustc-check-cfg=cfg(portable)");
+    let arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let native = matches!(
+        &*arch,
+        "x86_64" | "x86" | "riscv64" | "riscv32" | "aarch64" | "arm"
+    );
+    let miri = std::env::var_os("CARGO_CFG_MIRI").is_some();
+    let portable = miri || !native && std::env::var_os("CARGO_FEATURE_PORTABLE").is_some();
+    if portable {
+        println!("cargo::rustc-cfg=portable");
+    }
+
+    // Unwinding through inline assembly requires unstable `asm_unwind`. Only enable it when
+    // there is CFI to unwind with.
+    println!("cargo::rustc-check-cfg=cfg(native_unwind)");
+    if emit_cfi && native && !portable && std::env::var_os("CARGO_FEATURE_NIGHTLY").is_some() {
+        println!("cargo::rustc-cfg=native_unwind");
+    }
+
+    // Save and restore the FP environment only if there is an FPU. Some target features are
+    // unstable and invisible to `cfg(target_feature)` on stable, thus we also guess from the
+    // target name and ABI.
+    println!("cargo::rustc-check-cfg=cfg(fpenv)");
+    let features = std::env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    let has_feature = |f: &str| features.split(',').any(|x| x == f);
+    let target = std::env::var("TARGET").unwrap_or_default();
+    let has_fpu = match &*arch {
+        "x86_64" | "x86" => true,
+        "aarch64" => has_feature("neon"),
+        "riscv64" | "riscv32" => {
+            // eg. riscv64gc, riscv32imafc.
+            let isa = target.split('-').next().unwrap_or_default();
+            has_feature("f") || isa.get(7..).unwrap_or_default().contains(['g', 'f'])
+        }
+        "arm" => {
+            has_feature("vfp2")
+                || matches!(
+                    std::env::var("CARGO_CFG_TARGET_ABI").as_deref(),
+                    Ok("eabihf")
+                )
+        }
+        _ => false,
+    };
+    if has_fpu && std::env::var_os("CARGO_FEATURE_FPENV").is_some() {
+        println!("cargo::rustc-cfg=fpenv");
+    }
 }
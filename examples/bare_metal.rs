@@ -0,0 +1,121 @@
+//! Runtime test of feature `bare-metal` on riscv32 machine mode, run by:
+//!
+//! ```text
+//! RUSTFLAGS="-Cpanic=abort -Clink-arg=-T$PWD/examples/bare_metal.ld" \
+//!     cargo build --target=riscv32imac-unknown-none-elf --example=bare_metal --features=bare-metal
+//! qemu-system-riscv32 -machine virt -bios none -nographic \
+//!     -kernel target/riscv32imac-unknown-none-elf/debug/examples/bare_metal
+//! ```
+//!
+//! It exits QEMU with status 0 on success.
+#![cfg_attr(target_os = "none", no_std, no_main)]
+
+#[cfg(not(target_os = "none"))]
+fn main() {}
+
+#[cfg(target_os = "none")]
+mod bare {
+    use core::arch::{asm, global_asm};
+    use core::ops::ControlFlow;
+    use core::ptr;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+
+    use sjlj2::{JumpPoint, catch_long_jump};
+
+    /// The test finisher device of QEMU `virt` machine.
+    const FINISHER: *mut u32 = 0x10_0000 as *mut u32;
+
+    /// The `mcause` of an environment call from machine mode.
+    const ECALL_FROM_M: usize = 11;
+
+    global_asm!(
+        ".section .text.start",
+        ".global _start",
+        "_start:",
+        "la sp, _stack_top",
+        "la t0, 1f",
+        "csrw mtvec, t0",
+        "call {start}",
+        // Traps are handled on the same stack, and never return by `mret`.
+        // `mtvec` requires 4-byte alignment.
+        ".p2align 2",
+        "1:",
+        "j {trap}",
+        start = sym start,
+        trap = sym trap_handler,
+    );
+
+    /// The checkpoint to jump to on trap.
+    static TRAP_JP: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+    extern "C" fn trap_handler() -> ! {
+        let jp = TRAP_JP.load(Ordering::Relaxed);
+        if jp.is_null() {
+            exit(2);
+        }
+        let mcause: usize;
+        unsafe {
+            asm!("csrr {}, mcause", out(reg) mcause);
+            // SAFETY: The trapped closure only has POFs.
+            JumpPoint::from_raw(jp).long_jump(mcause)
+        }
+    }
+
+    fn exit(code: u32) -> ! {
+        let value = if code == 0 {
+            0x5555
+        } else {
+            (code << 16) | 0x3333
+        };
+        unsafe { FINISHER.write_volatile(value) };
+        loop {
+            unsafe { asm!("wfi") };
+        }
+    }
+
+    #[panic_handler]
+    fn panic_handler(_: &core::panic::PanicInfo<'_>) -> ! {
+        exit(1)
+    }
+
+    fn interrupt_enabled() -> bool {
+        let mstatus: usize;
+        unsafe { asm!("csrr {}, mstatus", out(reg) mstatus) };
+        mstatus & 8 != 0
+    }
+
+    fn set_interrupt_enabled(enabled: bool) {
+        // No interrupt source is enabled in `mie`, thus no interrupt is actually taken.
+        unsafe {
+            if enabled {
+                asm!("csrsi mstatus, 8");
+            } else {
+                asm!("csrci mstatus, 8");
+            }
+        }
+    }
+
+    extern "C" fn start() -> ! {
+        for enabled in [true, false] {
+            set_interrupt_enabled(enabled);
+
+            // Jump out of a trap handler, which is entered with interrupts disabled.
+            let ret = catch_long_jump(|jp| {
+                TRAP_JP.store(jp.as_raw(), Ordering::Relaxed);
+                unsafe { asm!("ecall") };
+            });
+            TRAP_JP.store(ptr::null_mut(), Ordering::Relaxed);
+            assert_eq!(ret, ControlFlow::Break(ECALL_FROM_M));
+            assert_eq!(interrupt_enabled(), enabled);
+
+            // Jump out of a critical section, like `critical_section::with` on single-hart riscv.
+            let ret = catch_long_jump(|jp| {
+                set_interrupt_enabled(false);
+                unsafe { jp.long_jump(42) }
+            });
+            assert_eq!(ret, ControlFlow::Break(42));
+            assert_eq!(interrupt_enabled(), enabled);
+        }
+        exit(0)
+    }
+}
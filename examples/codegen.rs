@@ -8,7 +8,9 @@ use sjlj2::catch_long_jump;
 
 #[cfg(not(feature = "default"))]
 #[panic_handler]
-fn panic_handler(_: &core::panic::PanicInfo<'_>) -> ! {
+fn panic_handler(info: &core::panic::PanicInfo<'_>) -> ! {
+    // SAFETY: Only `codegen_recovery` runs `run_with_recovery`, whose closure has only POFs.
+    unsafe { sjlj2::recover(info) };
     loop {}
 }
 
@@ -32,3 +34,9 @@ extern "C" fn codegen_no_jump() -> bool {
 extern "C" fn codegen_must_jump() -> bool {
     catch_long_jump::<Infallible, _>(|jp| unsafe { jp.long_jump(13) }).is_break()
 }
+
+#[unsafe(no_mangle)]
+extern "C" fn codegen_recovery(f: extern "C" fn()) -> bool {
+    // SAFETY: It is single-threaded.
+    unsafe { sjlj2::run_with_recovery(|| f()) }.is_err()
+}
@@ -0,0 +1,277 @@
+//! Procedural macros for [`sjlj2`](https://docs.rs/sjlj2).
+//!
+//! Use them via the re-exports in `sjlj2` with feature `macros`, instead of depending on this
+//! crate directly.
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+use syn::visit_mut::VisitMut;
+use syn::{
+    Expr, FnArg, Ident, ItemFn, Local, Pat, Path, PathArguments, Stmt, Type, parse_macro_input,
+    parse_quote, parse_quote_spanned,
+};
+
+/// Statically check that a function is a Plain Old Frame (POF), which is safe to be skipped by
+/// `long_jump`.
+///
+/// It is a best-effort check like the one on the closure of `catch_long_jump`. A compile error
+/// is generated if:
+///
+/// - Any argument, or any local variable introduced by `let`, has a type with significant
+///   `Drop`, detected by `core::mem::needs_drop`. Temporaries and bindings in `match`, `if let`
+///   and `for` are not checked.
+///
+/// - A `JumpPoint` argument is passed to a function call, and the callee is not marked with
+///   `#[pof]` either. Only calls via paths are checked, eg. `foo(jp)` or `Self::foo(jp)`.
+///   Method calls are not checked. `long_jump` and `long_jump_unwinding` are always allowed.
+///
+/// Same as the closure check, the type check is evaluated when the function is monomorphized.
+/// Functions which are never used are not checked, and errors in generic functions are reported
+/// at the use site.
+///
+/// Besides the function, it emits a hidden marker item named `__sjlj2_pof_<name>` in the
+/// same scope and with the same visibility, used to check callers. It cannot be used on
+/// trait methods, since the marker is not a member of the trait.
+///
+/// # Examples
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use sjlj2::{JumpPoint, catch_long_jump, pof};
+///
+/// #[pof]
+/// fn check(jp: JumpPoint<'_>, x: usize) -> usize {
+///     let y = x * 2;
+///     if y > 10 {
+///         // SAFETY: Only POFs in between.
+///         unsafe { jp.long_jump(y) }
+///     }
+///     y
+/// }
+///
+/// #[pof]
+/// fn outer(jp: JumpPoint<'_>) -> usize {
+///     check(jp, 1) + check(jp, 6)
+/// }
+///
+/// assert_eq!(catch_long_jump(outer), ControlFlow::Break(12));
+/// ```
+///
+/// Locals with significant `Drop` are rejected.
+///
+/// ```compile_fail
+/// use sjlj2::{JumpPoint, pof};
+///
+/// #[pof]
+/// fn leak(jp: JumpPoint<'_>) {
+///     let s = String::from("leaked");
+///     unsafe { jp.long_jump(s.len()) }
+/// }
+/// # sjlj2::catch_long_jump(leak);
+/// ```
+///
+/// So are calls passing `JumpPoint` to functions without `#[pof]`.
+///
+/// ```compile_fail
+/// use sjlj2::{JumpPoint, pof};
+///
+/// fn unchecked(jp: JumpPoint<'_>) {
+///     unsafe { jp.long_jump(1) }
+/// }
+///
+/// #[pof]
+/// fn caller(jp: JumpPoint<'_>) {
+///     unchecked(jp);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn pof(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            TokenStream::from(attr).span(),
+            "#[pof] does not accept arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+    let func = parse_macro_input!(item as ItemFn);
+    expand_pof(func).into()
+}
+
+fn expand_pof(mut func: ItemFn) -> TokenStream {
+    let mut checks = Vec::new();
+    let mut jump_points = Vec::new();
+    for arg in &func.sig.inputs {
+        match arg {
+            FnArg::Receiver(recv) => {
+                let self_token = &recv.self_token;
+                checks.push(assert_binding(&Ident::new("self", self_token.span)));
+            }
+            FnArg::Typed(arg) => {
+                let mut bindings = Vec::new();
+                collect_bindings(&arg.pat, &mut bindings);
+                if is_jump_point(&arg.ty)
+                    && let Pat::Ident(pat) = &*arg.pat
+                {
+                    jump_points.push(pat.ident.clone());
+                }
+                checks.extend(bindings.iter().map(assert_binding));
+            }
+        }
+    }
+
+    let mut checker = CallChecker {
+        jump_points: &jump_points,
+        markers: Vec::new(),
+    };
+    checker.visit_block(&func.block);
+    let markers = checker.markers;
+
+    LocalChecker.visit_block_mut(&mut func.block);
+
+    let stmts = &func.block.stmts;
+    func.block = parse_quote!({
+        #(#checks)*
+        #(#markers)*
+        #(#stmts)*
+    });
+
+    let vis = &func.vis;
+    let marker = format_ident!("__sjlj2_pof_{}", func.sig.ident);
+    quote! {
+        #func
+
+        #[doc(hidden)]
+        #[allow(non_upper_case_globals, dead_code)]
+        #vis const #marker: () = ();
+    }
+}
+
+/// Assert that a binding has no significant `Drop`, spanned at the binding.
+fn assert_binding(ident: &Ident) -> Stmt {
+    parse_quote_spanned! {ident.span()=>
+        ::sjlj2::__private::assert_pof(&#ident);
+    }
+}
+
+fn collect_bindings(pat: &Pat, out: &mut Vec<Ident>) {
+    struct Collector<'a>(&'a mut Vec<Ident>);
+
+    impl<'ast> Visit<'ast> for Collector<'_> {
+        fn visit_pat_ident(&mut self, pat: &'ast syn::PatIdent) {
+            self.0.push(pat.ident.clone());
+            syn::visit::visit_pat_ident(self, pat);
+        }
+
+        // Paths in patterns are constants or unit structs, not bindings.
+        fn visit_path(&mut self, _: &'ast Path) {}
+    }
+
+    Collector(out).visit_pat(pat);
+}
+
+fn is_jump_point(ty: &Type) -> bool {
+    match ty {
+        Type::Path(ty) => ty
+            .path
+            .segments
+            .last()
+            .is_some_and(|seg| seg.ident == "JumpPoint"),
+        Type::Group(ty) => is_jump_point(&ty.elem),
+        Type::Paren(ty) => is_jump_point(&ty.elem),
+        _ => false,
+    }
+}
+
+/// Inserts checks after each `let` statement, without entering nested items.
+struct LocalChecker;
+
+impl VisitMut for LocalChecker {
+    fn visit_block_mut(&mut self, block: &mut syn::Block) {
+        let stmts = std::mem::take(&mut block.stmts);
+        for mut stmt in stmts {
+            self.visit_stmt_mut(&mut stmt);
+            let checks = match &stmt {
+                Stmt::Local(local) => local_checks(local),
+                _ => Vec::new(),
+            };
+            block.stmts.push(stmt);
+            block.stmts.extend(checks);
+        }
+    }
+
+    fn visit_item_mut(&mut self, _: &mut syn::Item) {}
+}
+
+fn local_checks(local: &Local) -> Vec<Stmt> {
+    let (pat, ty) = match &local.pat {
+        Pat::Type(pat) => (&*pat.pat, Some(&*pat.ty)),
+        pat => (pat, None),
+    };
+    if local.init.is_some() {
+        let mut bindings = Vec::new();
+        collect_bindings(pat, &mut bindings);
+        bindings.iter().map(assert_binding).collect()
+    } else if let Some(ty) = ty {
+        // Uninitialized bindings cannot be borrowed, check the type instead.
+        vec![parse_quote_spanned! {ty.span()=>
+            ::sjlj2::__private::assert_pof_type::<#ty>();
+        }]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Collects markers of callees which receive a `JumpPoint`, without entering nested items.
+struct CallChecker<'a> {
+    jump_points: &'a [Ident],
+    markers: Vec<Stmt>,
+}
+
+impl CallChecker<'_> {
+    fn passes_jump_point(&self, args: &syn::punctuated::Punctuated<Expr, syn::Token![,]>) -> bool {
+        args.iter().any(|arg| match arg {
+            Expr::Path(arg) => arg
+                .path
+                .get_ident()
+                .is_some_and(|ident| self.jump_points.contains(ident)),
+            _ => false,
+        })
+    }
+}
+
+impl<'ast> Visit<'ast> for CallChecker<'_> {
+    fn visit_expr_call(&mut self, call: &'ast syn::ExprCall) {
+        syn::visit::visit_expr_call(self, call);
+        if !self.passes_jump_point(&call.args) {
+            return;
+        }
+        let Expr::Path(callee) = &*call.func else {
+            return;
+        };
+        if callee.qself.is_some() {
+            return;
+        }
+        let mut path = callee.path.clone();
+        let Some(last) = path.segments.last_mut() else {
+            return;
+        };
+        if last.ident == "long_jump" || last.ident == "long_jump_unwinding" {
+            return;
+        }
+        last.ident = format_ident!("__sjlj2_pof_{}", last.ident, span = last.ident.span());
+        for seg in &mut path.segments {
+            seg.arguments = PathArguments::None;
+        }
+        let span = callee.span();
+        self.markers.push(parse_quote_spanned! {span=>
+            let _: () = #path;
+        });
+    }
+
+    fn visit_item(&mut self, _: &'ast syn::Item) {}
+}
@@ -1,19 +1,70 @@
-// sp, x19, fp, lander
+/// The jump buffer layout on aarch64.
+///
+/// A jump is performed as follows, with `x1` pointing to the buffer and `x0` holding the
+/// payload. With feature `fpenv`, `fpcr` is restored first.
+///
+/// ```text
+/// ldp x2, x19, [x1]
+/// ldp fp, lr, [x1, #16]
+/// mov sp, x2
+/// str x0, [x1]
+/// ret
+/// ```
 #[repr(C, align(16))]
-pub(crate) struct Buf(pub [usize; 4]);
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct RawJumpBuf {
+    /// `sp`, and also the payload after jumping.
+    pub sp: usize,
+    /// `x19`.
+    pub x19: usize,
+    /// `fp` (`x29`).
+    pub fp: usize,
+    /// The address to jump to.
+    pub lander: usize,
+    /// FPCR, only with feature `fpenv` and FP enabled.
+    #[cfg(fpenv)]
+    pub fpcr: usize,
+}
+
+pub(crate) type Buf = RawJumpBuf;
 
 macro_rules! set_jump_raw {
     ($buf_ptr:expr, $func:path, $lander:block) => {
+        #[cfg(native_unwind)]
+        let jumped: usize;
         core::arch::asm!(
+            #[cfg(not(native_unwind))]
             "adr x1, {lander}",
+            #[cfg(native_unwind)]
+            "adr x1, 3f",
             "mov x2, sp",
             "stp x2, x19, [x0]",
             "stp fp, x1, [x0, #16]",
+            #[cfg(fpenv)]
+            "mrs x2, fpcr",
+            #[cfg(fpenv)]
+            "str x2, [x0, #32]",
             "bl {func}",
+            #[cfg(native_unwind)]
+            "mov x1, #0",
+            #[cfg(native_unwind)]
+            "b 4f",
+            #[cfg(native_unwind)]
+            "3:",
+            #[cfg(native_unwind)]
+            "mov x1, #1",
+            #[cfg(native_unwind)]
+            "4:",
 
             in("x0") $buf_ptr, // arg0
             func = sym $func,
+            #[cfg(not(native_unwind))]
             lander = label $lander,
+            #[cfg(native_unwind)]
+            lateout("x1") jumped,
+            #[cfg(native_unwind)]
+            options(may_unwind),
 
             // Callee saved registers.
             // lateout("sp") _, // sp
@@ -22,7 +73,7 @@ macro_rules! set_jump_raw {
 
             // On non-darwin platform, mark x18 clobbered.
             // It is platform-reserved on darwin: do not touch it at all.
-            // See: <https://stackoverflow.com/questions/71152539/consequence-of-violating-macoss-arm64-calling-convention>
+            // See: <https://stackoverflow.com/questions/71152539>
             #[cfg(not(target_os = "macos"))]
             lateout("x18") _,
 
@@ -40,7 +91,10 @@ macro_rules! set_jump_raw {
             lateout("lr") _,
             // Caller saved registers.
             clobber_abi("C"),
-        )
+        );
+        #[cfg(native_unwind)]
+        #[allow(unused_unsafe)]
+        if jumped != 0 $lander
     };
 }
 
@@ -53,6 +107,10 @@ pub(crate) unsafe fn long_jump_raw(jp: *mut (), data: usize) -> ! {
             #[cfg(emit_cfi)]
             ".cfi_undefined lr",
 
+            #[cfg(fpenv)]
+            "ldr x2, [x1, #32]",
+            #[cfg(fpenv)]
+            "msr fpcr, x2",
             "ldp x2, x19, [x1]",
             "ldp fp, lr, [x1, #16]",
             "mov sp, x2",
@@ -68,3 +126,34 @@ pub(crate) unsafe fn long_jump_raw(jp: *mut (), data: usize) -> ! {
         )
     }
 }
+
+/// Same as `long_jump_raw`, but store two words of payload.
+#[inline]
+pub(crate) unsafe fn long_jump_raw_pair(jp: *mut (), data: (usize, usize)) -> ! {
+    unsafe {
+        core::arch::asm!(
+            #[cfg(emit_cfi)]
+            ".cfi_remember_state",
+            #[cfg(emit_cfi)]
+            ".cfi_undefined lr",
+
+            #[cfg(fpenv)]
+            "ldr x2, [x1, #32]",
+            #[cfg(fpenv)]
+            "msr fpcr, x2",
+            "ldp x2, x19, [x1]",
+            "ldp fp, lr, [x1, #16]",
+            "mov sp, x2",
+            "stp x0, x3, [x1]",
+            "ret",
+
+            #[cfg(emit_cfi)]
+            ".cfi_restore_state",
+
+            in("x0") data.0,
+            in("x3") data.1,
+            in("x1") jp,
+            options(noreturn, nostack),
+        )
+    }
+}
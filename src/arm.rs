@@ -1,16 +1,67 @@
-// result, r6, r11, sp, lander
-pub(crate) struct Buf(pub [usize; 5]);
+/// The jump buffer layout on arm.
+///
+/// A jump is performed as follows, with `r1` pointing to the buffer and `r0` holding the
+/// payload. With feature `fpenv`, `fpscr` is restored first.
+///
+/// ```text
+/// str r0, [r1]
+/// ldm r1, {r0, r6, r11, sp, pc}
+/// ```
+#[repr(C)]
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct RawJumpBuf {
+    /// The payload after jumping.
+    pub payload: usize,
+    /// `r6`.
+    pub r6: usize,
+    /// `r11` (`fp`).
+    pub fp: usize,
+    /// `sp`.
+    pub sp: usize,
+    /// The address to jump to.
+    pub lander: usize,
+    /// FPSCR, only with feature `fpenv` and VFP enabled.
+    #[cfg(fpenv)]
+    pub fpscr: u32,
+}
+
+pub(crate) type Buf = RawJumpBuf;
 
 macro_rules! set_jump_raw {
     ($buf_ptr:expr, $func:path, $lander:block) => {
+        #[cfg(native_unwind)]
+        let jumped: usize;
         core::arch::asm!(
+            #[cfg(not(native_unwind))]
             "adr lr, {lander}",
+            #[cfg(native_unwind)]
+            "adr lr, 3f",
             "stm r0, {{r0, r6, r11, sp, lr}}",
+            #[cfg(fpenv)]
+            "vmrs r1, fpscr",
+            #[cfg(fpenv)]
+            "str r1, [r0, #20]",
             "bl {func}",
+            #[cfg(native_unwind)]
+            "mov r1, #0",
+            #[cfg(native_unwind)]
+            "b 4f",
+            #[cfg(native_unwind)]
+            "3:",
+            #[cfg(native_unwind)]
+            "mov r1, #1",
+            #[cfg(native_unwind)]
+            "4:",
 
             in("r0") $buf_ptr, // arg0
             func = sym $func,
+            #[cfg(not(native_unwind))]
             lander = label $lander,
+            #[cfg(native_unwind)]
+            lateout("r1") jumped,
+            #[cfg(native_unwind)]
+            options(may_unwind),
 
             // Callee saved registers.
             lateout("r4") _,
@@ -27,7 +78,10 @@ macro_rules! set_jump_raw {
             // Caller saved registers.
             // FIXME: inline asm clobber list contains reserved registers: D16-D31.
             clobber_abi("aapcs"),
-        )
+        );
+        #[cfg(native_unwind)]
+        #[allow(unused_unsafe)]
+        if jumped != 0 $lander
     };
 }
 
@@ -35,6 +89,10 @@ macro_rules! set_jump_raw {
 pub(crate) unsafe fn long_jump_raw(buf: *mut (), data: usize) -> ! {
     unsafe {
         core::arch::asm!(
+            #[cfg(fpenv)]
+            "ldr r2, [r1, #20]",
+            #[cfg(fpenv)]
+            "vmsr fpscr, r2",
             "str r0, [r1]",
             "ldm r1, {{r0, r6, r11, sp, pc}}",
             in("r0") data,
@@ -43,3 +101,24 @@ pub(crate) unsafe fn long_jump_raw(buf: *mut (), data: usize) -> ! {
         )
     }
 }
+
+/// Same as `long_jump_raw`, but store two words of payload.
+#[inline]
+pub(crate) unsafe fn long_jump_raw_pair(buf: *mut (), data: (usize, usize)) -> ! {
+    unsafe {
+        core::arch::asm!(
+            #[cfg(fpenv)]
+            "ldr r2, [r1, #20]",
+            #[cfg(fpenv)]
+            "vmsr fpscr, r2",
+            // r6 shares the slot with the second word.
+            "ldr r6, [r1, #4]",
+            "stm r1!, {{r0, r3}}",
+            "ldm r1, {{r11, sp, pc}}",
+            in("r0") data.0,
+            in("r3") data.1,
+            in("r1") buf,
+            options(noreturn, nostack, readonly),
+        )
+    }
+}
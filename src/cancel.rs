@@ -0,0 +1,142 @@
+use core::fmt;
+use core::ops::ControlFlow;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{JumpPoint, catch_long_jump};
+
+/// A cancellation flag shared between a [`catch_cancellable`] closure and its cancellers.
+///
+/// It is `Send` and `Sync`, and can be shared with other threads via reference, `Arc` or
+/// `static`.
+#[derive(Debug, Default)]
+pub struct CancelToken {
+    cancelled: AtomicBool,
+}
+
+impl CancelToken {
+    /// Create a new token that is not cancelled.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Request cancellation. The closure will jump out at the next [`CancelScope::check`].
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether cancellation is requested.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Clear the cancellation request, so that it can be reused.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+/// The error returned by [`catch_cancellable`] when the closure is cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+impl core::error::Error for Cancelled {}
+
+/// The scope of a [`catch_cancellable`] closure, for polling cancellation at safe points.
+#[derive(Debug)]
+pub struct CancelScope<'a> {
+    token: &'a CancelToken,
+    // Raw `JumpPoint`, which is only valid while `&self` is borrowed by the closure.
+    jp: *mut (),
+}
+
+impl<'a> CancelScope<'a> {
+    /// Get the token of this scope.
+    #[must_use]
+    pub fn token(&self) -> &'a CancelToken {
+        self.token
+    }
+
+    /// Jump out of the closure if cancellation is requested, or return otherwise.
+    ///
+    /// It is a single relaxed atomic load in the fast path.
+    ///
+    /// # Safety
+    ///
+    /// All stack frames between the current and the corresponding [`catch_cancellable`] must
+    /// be POFs. See [`long_jump`][crate::long_jump] for details.
+    #[track_caller]
+    #[inline]
+    pub unsafe fn check(&self) {
+        if self.token.is_cancelled() {
+            unsafe { cancel(JumpPoint::from_raw(self.jp)) }
+        }
+    }
+}
+
+#[cold]
+#[inline(never)]
+#[track_caller]
+unsafe fn cancel(jp: JumpPoint<'_>) -> ! {
+    unsafe { jp.long_jump(0) }
+}
+
+/// Invokes a closure which can be cancelled cooperatively via a [`CancelToken`].
+///
+/// This function returns `Ok` if the closure returns normally, or `Err(Cancelled)` if
+/// [`CancelScope::check`] is called after [`CancelToken::cancel`]. The token is not reset
+/// afterwards.
+///
+/// Unlike asynchronous interruption via signals, the closure only jumps out at `check`, thus
+/// frames only need to be POFs there.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`], the argument closure must not have a significant `Drop`.
+///
+/// # Errors
+///
+/// Returns [`Cancelled`] if the closure is cancelled.
+///
+/// # Examples
+///
+/// ```
+/// use sjlj2::{CancelToken, Cancelled, catch_cancellable};
+///
+/// let token = CancelToken::new();
+/// let ret = std::thread::scope(|s| {
+///     catch_cancellable(&token, |scope| {
+///         let token = scope.token();
+///         s.spawn(move || token.cancel());
+///         loop {
+///             // SAFETY: There is no frame between.
+///             unsafe { scope.check() };
+///         }
+///     })
+/// });
+/// assert_eq!(ret, Err::<(), _>(Cancelled));
+/// ```
+#[inline]
+pub fn catch_cancellable<'a, T, F>(token: &'a CancelToken, f: F) -> Result<T, Cancelled>
+where
+    F: FnOnce(&CancelScope<'a>) -> T,
+{
+    match catch_long_jump(|jp| {
+        f(&CancelScope {
+            token,
+            jp: jp.as_raw(),
+        })
+    }) {
+        ControlFlow::Continue(ret) => Ok(ret),
+        ControlFlow::Break(_) => Err(Cancelled),
+    }
+}
@@ -0,0 +1,111 @@
+use core::ops::ControlFlow;
+
+use crate::JumpPoint;
+
+/// A pair of catching and resuming unwinding, used by [`catch_long_jump_with`] to pass panics
+/// across its assembly boundary.
+///
+/// With feature `std`, `StdUnwind` implements it via `std::panic::catch_unwind` and
+/// `std::panic::resume_unwind`. For `no_std` environments with `panic=unwind`, it can be
+/// implemented with a custom unwinder.
+///
+/// If an unwinding escapes from [`CatchUnwind::catch_unwind`], the process aborts.
+pub trait CatchUnwind {
+    /// The payload of a caught unwinding.
+    type Payload;
+
+    /// Invokes a closure, capturing the cause of an unwinding if one occurs.
+    ///
+    /// # Errors
+    ///
+    /// Returns the payload if the closure unwinds.
+    fn catch_unwind<R, F>(f: F) -> Result<R, Self::Payload>
+    where
+        F: FnOnce() -> R;
+
+    /// Resume an unwinding with a payload from [`CatchUnwind::catch_unwind`].
+    fn resume_unwind(payload: Self::Payload) -> !;
+}
+
+/// [`CatchUnwind`] via [`std::panic::catch_unwind`] and [`std::panic::resume_unwind`].
+///
+/// This is what [`catch_long_jump`][crate::catch_long_jump] uses with feature `unwind`. This
+/// type requires feature `std`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdUnwind;
+
+#[cfg(feature = "std")]
+impl CatchUnwind for StdUnwind {
+    type Payload = std::boxed::Box<dyn core::any::Any + Send>;
+
+    #[inline]
+    fn catch_unwind<R, F>(f: F) -> Result<R, Self::Payload>
+    where
+        F: FnOnce() -> R,
+    {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+    }
+
+    #[inline]
+    fn resume_unwind(payload: Self::Payload) -> ! {
+        std::panic::resume_unwind(payload)
+    }
+}
+
+/// Invokes a closure with a jump checkpoint, passing panics through via a [`CatchUnwind`].
+///
+/// Same as [`catch_long_jump`][crate::catch_long_jump], except that panics in the closure are
+/// caught by `U` inside the assembly boundary, and resumed by `U` after it, regardless of
+/// feature `unwind`. The portable implementation already passes through panics, thus `U` is
+/// unused there.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`][crate::catch_long_jump], the argument closure must not have a
+/// significant `Drop`.
+///
+/// # Examples
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use sjlj2::{CatchUnwind, catch_long_jump_with};
+///
+/// struct MyUnwind;
+///
+/// impl CatchUnwind for MyUnwind {
+///     type Payload = Box<dyn std::any::Any + Send>;
+///
+///     fn catch_unwind<R, F: FnOnce() -> R>(f: F) -> Result<R, Self::Payload> {
+///         std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+///     }
+///
+///     fn resume_unwind(payload: Self::Payload) -> ! {
+///         std::panic::resume_unwind(payload)
+///     }
+/// }
+///
+/// let ret = catch_long_jump_with::<MyUnwind, _, _>(|jp| unsafe { jp.long_jump(42) });
+/// assert_eq!(ret, ControlFlow::<_, ()>::Break(42));
+///
+/// let ret = std::panic::catch_unwind(|| {
+///     catch_long_jump_with::<MyUnwind, (), _>(|_jp| panic!("boom"))
+/// });
+/// assert!(ret.is_err());
+/// ```
+#[inline]
+pub fn catch_long_jump_with<U, T, F>(f: F) -> ControlFlow<usize, T>
+where
+    U: CatchUnwind,
+    F: FnOnce(JumpPoint<'_>) -> T,
+{
+    #[cfg(not(portable))]
+    {
+        crate::catch_long_jump_unwind::<U, _, _, _>(f)
+    }
+
+    #[cfg(portable)]
+    {
+        crate::catch_long_jump_plain(f)
+    }
+}
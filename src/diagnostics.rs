@@ -0,0 +1,120 @@
+use core::ops::ControlFlow;
+use core::panic::Location;
+
+use crate::{Data, JumpPoint, catch_long_jump};
+
+/// Where a [`long_jump`][crate::long_jump] came from, recorded before jumping.
+pub(crate) struct JumpSite {
+    location: &'static Location<'static>,
+    #[cfg(feature = "std")]
+    backtrace: std::backtrace::Backtrace,
+}
+
+/// The slot in the checkpoint to store a [`JumpSite`], or null if it is not traced.
+pub(crate) type SiteSlot = *mut Option<JumpSite>;
+
+fn slot_of(buf: *mut ()) -> *mut SiteSlot {
+    buf.wrapping_byte_add(core::mem::offset_of!(Data<()>, site))
+        .cast()
+}
+
+/// Record the caller into the checkpoint `buf`, if it is from [`catch_long_jump_traced`].
+#[track_caller]
+#[inline]
+pub(crate) unsafe fn record(buf: *mut ()) {
+    let slot = unsafe { slot_of(buf).read() };
+    if !slot.is_null() {
+        let site = JumpSite {
+            location: Location::caller(),
+            #[cfg(feature = "std")]
+            backtrace: std::backtrace::Backtrace::capture(),
+        };
+        // SAFETY: The slot outlives the checkpoint, which is still alive.
+        unsafe { *slot = Some(site) };
+    }
+}
+
+/// The `Break` result of [`catch_long_jump_traced`], with where the jump came from.
+#[derive(Debug)]
+pub struct JumpInfo {
+    data: usize,
+    location: Option<&'static Location<'static>>,
+    #[cfg(feature = "std")]
+    backtrace: Option<std::backtrace::Backtrace>,
+}
+
+impl JumpInfo {
+    /// Get the carried value of the jump.
+    #[must_use]
+    pub fn data(&self) -> usize {
+        self.data
+    }
+
+    /// Get the caller location of [`long_jump`][crate::long_jump].
+    ///
+    /// Wrappers, eg. [`CancelScope::check`][crate::CancelScope::check], report their callers. It
+    /// is `None` if the jump is not from `long_jump` or its wrappers, eg. from foreign code via
+    /// [`RawJumpBuf`][crate::RawJumpBuf], or from the signal handler of feature `interrupt`.
+    #[must_use]
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.location
+    }
+
+    /// Get the backtrace captured by [`long_jump`][crate::long_jump] via
+    /// `std::backtrace::Backtrace::capture`, which is disabled unless `RUST_BACKTRACE` or
+    /// `RUST_LIB_BACKTRACE` is set. This method requires feature `std`.
+    ///
+    /// It is `None` in the same cases as [`JumpInfo::location`].
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn backtrace(&self) -> Option<&std::backtrace::Backtrace> {
+        self.backtrace.as_ref()
+    }
+}
+
+/// Invokes a closure with a jump checkpoint, and records where the jump came from.
+///
+/// Same as [`catch_long_jump`], except that `Break` carries a [`JumpInfo`] with the caller
+/// location of [`long_jump`][crate::long_jump], and a backtrace with feature `std`.
+/// This function requires feature `diagnostics`.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`], the argument closure must not have a significant `Drop`.
+///
+/// # Examples
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use sjlj2::catch_long_jump_traced;
+///
+/// let line = line!();
+/// let ret = catch_long_jump_traced(|jp| -> () { unsafe { jp.long_jump(42) } });
+/// let ControlFlow::Break(info) = ret else { unreachable!() };
+/// assert_eq!(info.data(), 42);
+/// assert_eq!(info.location().unwrap().line(), line + 1);
+/// ```
+#[inline]
+pub fn catch_long_jump_traced<T, F>(f: F) -> ControlFlow<JumpInfo, T>
+where
+    F: FnOnce(JumpPoint<'_>) -> T,
+{
+    let mut site = None;
+    let site_ptr: SiteSlot = &raw mut site;
+    match catch_long_jump(|jp| {
+        // SAFETY: `jp` points to a live checkpoint, and `site` outlives it.
+        unsafe { slot_of(jp.as_raw()).write(site_ptr) };
+        f(jp)
+    }) {
+        ControlFlow::Continue(ret) => ControlFlow::Continue(ret),
+        ControlFlow::Break(data) => {
+            let site = site.take();
+            ControlFlow::Break(JumpInfo {
+                data,
+                location: site.as_ref().map(|site| site.location),
+                #[cfg(feature = "std")]
+                backtrace: site.map(|site| site.backtrace),
+            })
+        }
+    }
+}
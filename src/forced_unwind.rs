@@ -0,0 +1,169 @@
+//! Force unwinding to a checkpoint via the Itanium C++ ABI unwinder.
+//!
+//! The unwinder walks frames from the `long_jump_unwinding` call site outwards and executes
+//! landing pads (thus `Drop`s) of each frame. Our stop function is consulted before each frame
+//! and performs the ordinary `long_jump_raw` once the boundary frame is reached.
+//!
+//! The boundary is the address of a stack slot in the outlined frame invoking the closure, see
+//! [`call_with_boundary`]. In the stop function, `_Unwind_GetCFA` gives the stack pointer of the
+//! frame to be unwound, which is below its own stack slots but above those of its callees.
+//! Since the stack grows downwards on all supported architectures, frames with it below or equal
+//! to the boundary are inside the closure and need cleanups, while the first frame above it is
+//! where the catch (and possibly `catch_unwind`) lives.
+//!
+//! The exception object must outlive all landing pads, which reuse the stack space of frames
+//! already unwound. Thus it is stored in the catch frame, in [`UnwindState`] next to `jmp_buf`.
+use core::ffi::{c_int, c_void};
+
+use crate::{Data, JumpPoint, imp};
+
+#[repr(C)]
+struct UnwindContext {
+    _opaque: [u8; 0],
+}
+
+type UnwindStopFn = unsafe extern "C" fn(
+    version: c_int,
+    actions: c_int,
+    exception_class: u64,
+    exception: *mut UnwindException,
+    context: *mut UnwindContext,
+    stop_parameter: *mut c_void,
+) -> c_int;
+
+// Over-sized private area is harmless, it only needs to be large enough for all unwinders.
+#[repr(C, align(16))]
+struct UnwindException {
+    exception_class: u64,
+    exception_cleanup: Option<unsafe extern "C" fn(c_int, *mut UnwindException)>,
+    private: [usize; 6],
+}
+
+const URC_NO_REASON: c_int = 0;
+const UA_END_OF_STACK: c_int = 16;
+
+// "SJLJ2\0\0\0" in big endian, mimicking "MOZ\0RUST" of Rust panics.
+const EXCEPTION_CLASS: u64 = u64::from_be_bytes(*b"SJLJ2\0\0\0");
+
+unsafe extern "C-unwind" {
+    fn _Unwind_ForcedUnwind(
+        exception: *mut UnwindException,
+        stop: UnwindStopFn,
+        stop_parameter: *mut c_void,
+    ) -> c_int;
+}
+
+unsafe extern "C" {
+    fn _Unwind_GetCFA(context: *mut UnwindContext) -> usize;
+}
+
+/// Per-checkpoint state, stored right after `jmp_buf`. See `Data` in `set_jump_impl`.
+#[repr(C)]
+pub(crate) struct UnwindState {
+    exception: UnwindException,
+    boundary: usize,
+    data: usize,
+}
+
+fn state_of(buf: *mut ()) -> *mut UnwindState {
+    buf.wrapping_byte_add(core::mem::offset_of!(Data<()>, unwind_state))
+        .cast()
+}
+
+/// Invoke `f` in a separately unwindable frame, and record the boundary frame for
+/// [`long_jump_unwinding`].
+// It must be outlined from the catching frame, or the catch pad of `catch_unwind` would be
+// inside the boundary. The escaping `marker` also prevents `f` from being tail-called.
+#[inline(never)]
+pub(crate) fn call_with_boundary<P, T, F>(f: F, jp: JumpPoint<'_, P>) -> T
+where
+    F: FnOnce(JumpPoint<'_, P>) -> T,
+{
+    let marker = 0u8;
+    // SAFETY: `jp` is created by `set_jump_impl` with a valid `UnwindState`.
+    unsafe { (&raw mut (*state_of(jp.0)).boundary).write(&raw const marker as usize) };
+    f(jp)
+}
+
+unsafe extern "C" fn stop(
+    _version: c_int,
+    actions: c_int,
+    _exception_class: u64,
+    _exception: *mut UnwindException,
+    context: *mut UnwindContext,
+    stop_parameter: *mut c_void,
+) -> c_int {
+    let buf = stop_parameter.cast::<()>();
+    let state = state_of(buf);
+    // Panicking in a non-unwinding function aborts.
+    assert!(
+        actions & UA_END_OF_STACK == 0,
+        "long_jump_unwinding reached the end of stack without finding the checkpoint",
+    );
+    let sp = unsafe { _Unwind_GetCFA(context) };
+    if sp > unsafe { (*state).boundary } {
+        // All frames inside the closure are cleaned up. The remaining ones are POFs.
+        unsafe { imp::long_jump_raw(buf, (*state).data) }
+    }
+    URC_NO_REASON
+}
+
+/// Long jump to a checkpoint like [`long_jump`][crate::long_jump], but run cleanups (`Drop`s) of
+/// all stack frames being skipped via forced unwinding.
+///
+/// It is much slower than [`long_jump`][crate::long_jump] since it walks the stack with the
+/// platform unwinder, but it does not require skipped frames to be POFs.
+/// Unlike panics, it cannot be intercepted by intermediate frames, thus this is suitable
+/// for cancellation.
+///
+/// This function requires cargo feature `forced-unwind`. It is only supported on platforms
+/// using the Itanium C++ ABI unwinder (`_Unwind_ForcedUnwind`), eg. Linux and macOS. The
+/// feature makes every [`catch_long_jump`][crate::catch_long_jump] invoke its closure through
+/// an outlined frame marking the unwinding boundary, and reserve the unwinding state next to
+/// the jump buffer, whether or not this function is used.
+///
+/// Note: Cleanups are only executed if there are landing pads, that is, when compiled with
+/// `panic=unwind`. Otherwise, it degrades to [`long_jump`][crate::long_jump] that skips all
+/// `Drop`s, which leaks resources but is still safe.
+///
+/// # Safety
+///
+/// All frames between the current and the `catch_long_jump` specified by `point` must be
+/// unwindable, that is, they must not have a non-unwinding ABI (eg. `extern "C"`) and must not
+/// be inside another [`catch_long_jump`][crate::catch_long_jump] or
+/// `std::panic::catch_unwind`. Otherwise the process aborts, or the behavior is undefined
+/// for foreign frames without unwinding information.
+///
+/// # Panics
+///
+/// The process aborts if the unwinder fails, or `point` is not found on the stack.
+#[doc(alias = "_Unwind_ForcedUnwind")]
+#[track_caller]
+pub unsafe fn long_jump_unwinding(point: JumpPoint<'_>, data: usize) -> ! {
+    #[cfg(feature = "diagnostics")]
+    unsafe {
+        crate::diagnostics::record(point.0);
+    }
+    #[cfg(feature = "tracing")]
+    unsafe {
+        crate::trace::jump(point.0, &data);
+    }
+    let state = state_of(point.0);
+    unsafe {
+        (&raw mut (*state).data).write(data);
+        (&raw mut (*state).exception).write(UnwindException {
+            exception_class: EXCEPTION_CLASS,
+            exception_cleanup: None,
+            private: [0; 6],
+        });
+        _Unwind_ForcedUnwind(&raw mut (*state).exception, stop, point.0.cast());
+    }
+    // Some cleanups may already be executed. There is no way to recover.
+    unwind_failed()
+}
+
+#[cold]
+extern "C" fn unwind_failed() -> ! {
+    // Panicking in a non-unwinding function aborts.
+    panic!("long_jump_unwinding failed to unwind the stack");
+}
@@ -0,0 +1,96 @@
+//! Restore callee-saved registers on `long_jump` by walking frames, instead of saving them on
+//! `catch_long_jump`.
+//!
+//! With feature `frame-walk`, `set_jump_raw!` only saves the stack pointer and the lander, and
+//! does not clobber callee-saved registers. They stay alive across the closure call, and any
+//! callee modifying them spills and describes them in its CFI. On `long_jump`, we walk frames with
+//! `_Unwind_Backtrace` up to the catch frame, where `_Unwind_GetGR` gives the values of these
+//! registers at the call site, recovered from spill slots of intermediate frames.
+//!
+//! The catch frame is identified by the saved stack pointer, which is the one right before `call`.
+//! During `_Unwind_Backtrace`, `_Unwind_GetCFA` returns the stack pointer of the frame being
+//! visited, both for libgcc and LLVM libunwind, thus the catch frame is the one whose CFA equals
+//! the saved stack pointer.
+use core::ffi::{c_int, c_void};
+
+#[repr(C)]
+struct UnwindContext {
+    _opaque: [u8; 0],
+}
+
+type UnwindTraceFn = unsafe extern "C" fn(context: *mut UnwindContext, arg: *mut c_void) -> c_int;
+
+const URC_NO_REASON: c_int = 0;
+const URC_NORMAL_STOP: c_int = 4;
+
+unsafe extern "C" {
+    fn _Unwind_Backtrace(trace: UnwindTraceFn, arg: *mut c_void) -> c_int;
+    fn _Unwind_GetCFA(context: *mut UnwindContext) -> usize;
+    fn _Unwind_GetGR(context: *mut UnwindContext, index: c_int) -> usize;
+}
+
+/// DWARF register numbers of rbx, rbp, r12, r13, r14 and r15.
+const REGS: [c_int; 6] = [3, 6, 12, 13, 14, 15];
+
+struct Walk {
+    sp: usize,
+    regs: Option<[usize; 6]>,
+}
+
+unsafe extern "C" fn trace(context: *mut UnwindContext, arg: *mut c_void) -> c_int {
+    let walk = unsafe { &mut *arg.cast::<Walk>() };
+    if unsafe { _Unwind_GetCFA(context) } != walk.sp {
+        return URC_NO_REASON;
+    }
+    walk.regs = Some(REGS.map(|reg| unsafe { _Unwind_GetGR(context, reg) }));
+    URC_NORMAL_STOP
+}
+
+/// Recover callee-saved registers of the catch frame with the saved stack pointer `sp`.
+// Outlined to keep the slow path out of callers of `long_jump`. It is `extern "C"` so that the
+// panic below aborts, instead of unwinding through frames which expect to be jumped over.
+#[inline(never)]
+#[cold]
+extern "C" fn recover(sp: usize, regs: &mut [usize; 6]) {
+    let mut walk = Walk { sp, regs: None };
+    unsafe { _Unwind_Backtrace(trace, (&raw mut walk).cast()) };
+    *regs = walk
+        .regs
+        .expect("long_jump reached the end of stack without finding the checkpoint");
+}
+
+#[inline]
+pub(crate) unsafe fn long_jump_raw(buf: *mut crate::imp::Buf, data: (usize, usize)) -> ! {
+    let mut regs = [0; 6];
+    recover(unsafe { (*buf).sp }, &mut regs);
+    unsafe {
+        core::arch::asm!(
+            #[cfg(emit_cfi)]
+            ".cfi_remember_state",
+            #[cfg(emit_cfi)]
+            ".cfi_undefined rip",
+            #[cfg(fpenv)]
+            "ldmxcsr dword ptr [rcx + 32]",
+            #[cfg(fpenv)]
+            "fldcw word ptr [rcx + 36]",
+            "mov rbx, [rsi     ]",
+            "mov rbp, [rsi +  8]",
+            "mov r12, [rsi + 16]",
+            "mov r13, [rsi + 24]",
+            "mov r14, [rsi + 32]",
+            "mov r15, [rsi + 40]",
+            "mov rsp, [rcx +  8]",
+            "mov [rcx], rax",
+            "mov [rcx + 8], rdx",
+            "jmp qword ptr [rcx + 24]",
+            #[cfg(emit_cfi)]
+            ".cfi_restore_state",
+
+            in("cx") buf,
+            in("si") &raw const regs,
+            in("ax") data.0,
+            in("dx") data.1,
+            options(noreturn),
+        )
+    }
+}
@@ -0,0 +1,91 @@
+use core::future::Future;
+use core::ops::ControlFlow;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::with_jump_scope;
+
+/// A future adapter which runs each `poll` of the inner future inside [`with_jump_scope`].
+///
+/// Code inside the inner future can [`throw`][crate::throw] to complete this future with
+/// `Break`, instead of tearing down the executor. It completes with `Continue` if the inner
+/// future completes normally. This type requires feature `std`.
+///
+/// During a `poll`, the inner future can reach the [`JumpPoint`][crate::JumpPoint] of its scope
+/// via [`with_jump_point`][crate::with_jump_point], eg. to pass it down to foreign code. The
+/// scope only lives during a single `poll`, and the jump point cannot escape the accessor, thus
+/// no jump point survives across `Poll::Pending`. A `throw` between polls goes to an outer
+/// scope, or panics if there is none.
+///
+/// On a jump, the inner future is interrupted in the middle of `poll`, and dropped right after
+/// it lands, in the state it is last suspended in.
+///
+/// # Precondition
+///
+/// All stack frames between the inner `poll` and `throw` must be POFs, as required by
+/// [`throw`][crate::throw].
+///
+/// Since the frame of the inner `poll` itself is skipped, the inner future does not track what
+/// it moves or drops there. Values it captures or holds across `.await` must not be moved out
+/// or dropped by a `poll` which throws, or they are dropped again. The portable implementation
+/// unwinds through the inner `poll` instead, thus it is dropped as after a caught panic.
+///
+/// # Examples
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use std::pin::pin;
+/// use std::task::{Context, Poll, Waker};
+/// use sjlj2::{JumpFuture, throw};
+///
+/// fn plugin(x: usize) -> usize {
+///     if x == 0 {
+///         // SAFETY: There are only POFs between.
+///         unsafe { throw(42) };
+///     }
+///     x
+/// }
+///
+/// let mut fut = pin!(JumpFuture::new(async { plugin(0) }));
+/// let mut cx = Context::from_waker(Waker::noop());
+/// assert_eq!(fut.as_mut().poll(&mut cx), Poll::Ready(ControlFlow::Break(42)));
+/// ```
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct JumpFuture<F> {
+    inner: Option<F>,
+}
+
+impl<F: Future> JumpFuture<F> {
+    /// Wrap a future.
+    pub fn new(fut: F) -> Self {
+        Self { inner: Some(fut) }
+    }
+}
+
+impl<F: Future> Future for JumpFuture<F> {
+    type Output = ControlFlow<usize, F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: `inner` is structurally pinned, and only dropped in place.
+        let this = unsafe { self.get_unchecked_mut() };
+        let fut = this
+            .inner
+            .as_mut()
+            .expect("`JumpFuture` polled after completion");
+        // SAFETY: See above.
+        let mut fut = unsafe { Pin::new_unchecked(fut) };
+        match with_jump_scope(|| fut.as_mut().poll(cx)) {
+            ControlFlow::Continue(Poll::Pending) => Poll::Pending,
+            ControlFlow::Continue(Poll::Ready(ret)) => {
+                this.inner = None;
+                Poll::Ready(ControlFlow::Continue(ret))
+            }
+            ControlFlow::Break(data) => {
+                // Dropped in the lander rather than leaked. See the precondition.
+                this.inner = None;
+                Poll::Ready(ControlFlow::Break(data))
+            }
+        }
+    }
+}
@@ -0,0 +1,530 @@
+//! Asynchronous interruption from other threads via signals.
+//!
+//! Each [`catch_interruptible`] scope links a node, living in its closure frame, into a
+//! thread-local intrusive list. [`InterruptHandle::interrupt`] marks the shared state as
+//! requested and sends [`interrupt_signal`] to the target thread via `tgkill`. The signal handler
+//! then searches the list of the current thread for a requested scope, and `long_jump`s to the
+//! innermost linked scope, which is the only one whose closure frames can be skipped.
+//!
+//! The signal is blocked outside of closures, ie. while setting up and cleaning up each scope,
+//! so that these non-POF frames are never skipped. If the innermost scope is jumped to on behalf
+//! of an outer one, the handler re-sends the signal to the current thread. It stays pending
+//! until the inner scope completes and unblocks it, then the handler runs again for the outer
+//! scope.
+//!
+//! [`catch_long_jump_with_timeout`] works the same way, except that the signal is sent by a
+//! per-thread POSIX timer. The timer is always armed at the earliest deadline of all linked
+//! scopes, and the signal handler checks for expired ones when it receives `SI_TIMER`.
+//!
+//! A signal arriving after the scope is unlinked, or to an unrelated thread because of TID reuse,
+//! finds nothing and is simply ignored.
+use std::cell::Cell;
+use std::ffi::{c_int, c_void};
+use std::fmt;
+use std::ops::ControlFlow;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::Once;
+use std::sync::atomic::{AtomicU8, Ordering, compiler_fence};
+use std::time::Duration;
+
+use crate::{JumpPoint, catch_long_jump};
+
+const RUNNING: u8 = 0;
+const REQUESTED: u8 = 1;
+const TIMED_OUT: u8 = 2;
+const FINISHED: u8 = 3;
+
+// Not exposed by `libc` for all Linux targets.
+const SIGEV_THREAD_ID: c_int = 4;
+
+#[derive(Debug)]
+struct Shared {
+    pid: libc::pid_t,
+    tid: libc::pid_t,
+    state: AtomicU8,
+}
+
+struct Node {
+    shared: *const Shared,
+    /// The deadline on `CLOCK_MONOTONIC`, for timeout scopes.
+    deadline: Option<Duration>,
+    jp: *mut (),
+    prev: *const Node,
+}
+
+std::thread_local! {
+    static HEAD: Cell<*const Node> = const { Cell::new(ptr::null()) };
+    // Copy of the `TIMER` ID, which is accessible in the signal handler.
+    static TIMER_ID: Cell<Option<libc::timer_t>> = const { Cell::new(None) };
+    static TIMER: ThreadTimer = ThreadTimer::new();
+}
+
+/// The per-thread timer sending [`interrupt_signal`] to its owner thread, created on demand.
+struct ThreadTimer(libc::timer_t);
+
+impl ThreadTimer {
+    fn new() -> Self {
+        let mut timer = ptr::null_mut();
+        unsafe {
+            let mut sev: libc::sigevent = std::mem::zeroed();
+            sev.sigev_notify = SIGEV_THREAD_ID;
+            sev.sigev_signo = interrupt_signal();
+            sev.sigev_notify_thread_id = libc::gettid();
+            let ret = libc::timer_create(libc::CLOCK_MONOTONIC, &raw mut sev, &raw mut timer);
+            assert_eq!(
+                ret,
+                0,
+                "failed to create the timeout timer: {}",
+                std::io::Error::last_os_error(),
+            );
+        }
+        TIMER_ID.with(|id| id.set(Some(timer)));
+        Self(timer)
+    }
+}
+
+impl Drop for ThreadTimer {
+    fn drop(&mut self) {
+        TIMER_ID.with(|id| id.set(None));
+        unsafe { libc::timer_delete(self.0) };
+    }
+}
+
+fn now() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &raw mut ts) };
+    // Lints of these casts vary with the width of `time_t` and `c_long` on the target.
+    #[allow(
+        clippy::cast_sign_loss,
+        clippy::cast_possible_truncation,
+        reason = "monotonic clock is non-negative and nanoseconds are less than 1e9"
+    )]
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+fn set_signal_blocked(blocked: bool) {
+    unsafe {
+        let mut set = std::mem::zeroed();
+        libc::sigemptyset(&raw mut set);
+        libc::sigaddset(&raw mut set, interrupt_signal());
+        let how = if blocked {
+            libc::SIG_BLOCK
+        } else {
+            libc::SIG_UNBLOCK
+        };
+        libc::pthread_sigmask(how, &raw const set, ptr::null_mut());
+    }
+}
+
+/// Blocks [`interrupt_signal`] until dropped. Pending signals are delivered on drop, and may
+/// `long_jump` out of it, thus it must be the first local of a scope, to be dropped last.
+struct BlockSignal;
+
+impl BlockSignal {
+    fn new() -> Self {
+        set_signal_blocked(true);
+        Self
+    }
+}
+
+impl Drop for BlockSignal {
+    fn drop(&mut self) {
+        set_signal_blocked(false);
+    }
+}
+
+/// Arm the per-thread timer, if any, at the earliest deadline of all linked scopes, or disarm it
+/// if there is none. This is async-signal-safe.
+fn rearm_timer() {
+    let Some(timer) = TIMER_ID.with(Cell::get) else {
+        return;
+    };
+    let mut deadline = None::<Duration>;
+    let mut node = HEAD.with(Cell::get);
+    while !node.is_null() {
+        let n = unsafe { &*node };
+        if let Some(d) = n.deadline {
+            deadline = Some(deadline.map_or(d, |cur| cur.min(d)));
+        }
+        node = n.prev;
+    }
+    // Zero disarms the timer. Avoid it for a real deadline, which is unlikely anyway.
+    let deadline = deadline.map_or(Duration::ZERO, |d| d.max(Duration::from_nanos(1)));
+    // `c_long` is 32-bit on 32-bit targets, thus `From<u32>` is unavailable.
+    #[allow(
+        clippy::cast_lossless,
+        clippy::cast_possible_wrap,
+        reason = "nanoseconds are less than 1e9"
+    )]
+    let nsec = deadline.subsec_nanos() as libc::c_long;
+    let spec = libc::itimerspec {
+        it_interval: libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        it_value: libc::timespec {
+            tv_sec: deadline.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_nsec: nsec,
+        },
+    };
+    unsafe { libc::timer_settime(timer, libc::TIMER_ABSTIME, &raw const spec, ptr::null_mut()) };
+}
+
+/// A handle to interrupt a running [`catch_interruptible`] closure, possibly from another thread.
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    shared: Arc<Shared>,
+}
+
+impl InterruptHandle {
+    /// Request to interrupt the corresponding [`catch_interruptible`] closure.
+    ///
+    /// Returns `true` if the request is sent, or `false` if the closure already finished or
+    /// another request was sent before. The interruption happens asynchronously when the signal
+    /// is delivered to the target thread.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the signal cannot be sent, which should not happen in practice.
+    #[allow(clippy::must_use_candidate, reason = "called for side effects")]
+    pub fn interrupt(&self) -> bool {
+        if self
+            .shared
+            .state
+            .compare_exchange(RUNNING, REQUESTED, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        // There is no libc wrapper for `tgkill` on older glibc.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_tgkill,
+                self.shared.pid,
+                self.shared.tid,
+                interrupt_signal(),
+            )
+        };
+        // The target thread may already exit, which is fine.
+        assert!(
+            ret == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH),
+            "failed to send the interrupt signal: {}",
+            std::io::Error::last_os_error(),
+        );
+        true
+    }
+
+    /// Returns whether the corresponding [`catch_interruptible`] closure has finished, either
+    /// normally or by interruption.
+    #[must_use]
+    pub fn is_finished(&self) -> bool {
+        self.shared.state.load(Ordering::SeqCst) == FINISHED
+    }
+}
+
+/// The error returned by [`catch_interruptible`] when the closure is interrupted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interrupted;
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// The error returned by [`catch_long_jump_with_timeout`] when the closure times out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("timed out")
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
+/// The real-time signal used for interruption, which is `SIGRTMAX - 1`.
+///
+/// The signal handler is installed on the first call to [`catch_interruptible`] or
+/// [`catch_long_jump_with_timeout`], replacing any
+/// existing one. Do not use this signal for other purposes.
+#[must_use]
+pub fn interrupt_signal() -> c_int {
+    libc::SIGRTMAX() - 1
+}
+
+fn install_handler() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| unsafe {
+        let mut act: libc::sigaction = std::mem::zeroed();
+        act.sa_sigaction = handler as *const () as usize;
+        act.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        libc::sigemptyset(&raw mut act.sa_mask);
+        let ret = libc::sigaction(interrupt_signal(), &raw const act, ptr::null_mut());
+        assert_eq!(
+            ret,
+            0,
+            "failed to install the interrupt signal handler: {}",
+            std::io::Error::last_os_error(),
+        );
+    });
+}
+
+extern "C" fn handler(_sig: c_int, info: *mut libc::siginfo_t, uctx: *mut c_void) {
+    // NB. Only async-signal-safe operations are allowed here.
+    let is_timer = unsafe { (*info).si_code } == libc::SI_TIMER;
+    let now = if is_timer { Some(now()) } else { None };
+
+    // We are inside the closure of the innermost scope, if any, since the signal is blocked
+    // elsewhere. Only its closure frames can be skipped, thus we always jump to it. If it is
+    // not the only requested one, it re-raises the signal for outer ones after completion.
+    let innermost = HEAD.with(Cell::get);
+    let mut requested = 0usize;
+    let mut node = innermost;
+    while !node.is_null() {
+        let n = unsafe { &*node };
+        let state = unsafe { &(*n.shared).state };
+        if let (Some(now), Some(deadline)) = (now, n.deadline)
+            && deadline <= now
+        {
+            let _ = state.compare_exchange(RUNNING, TIMED_OUT, Ordering::SeqCst, Ordering::Relaxed);
+        }
+        if matches!(state.load(Ordering::SeqCst), REQUESTED | TIMED_OUT) {
+            requested += 1;
+        }
+        node = n.prev;
+    }
+
+    if requested == 0 {
+        // The deadline may belong to a scope already completed.
+        if is_timer {
+            rearm_timer();
+        }
+        return;
+    }
+    let n = unsafe { &*innermost };
+    let state = unsafe { (*n.shared).state.load(Ordering::SeqCst) };
+    HEAD.with(|head| head.set(n.prev));
+    compiler_fence(Ordering::SeqCst);
+    unsafe {
+        // The signal is blocked during the handler, so it stays pending until the target scope
+        // unblocks it.
+        if requested > usize::from(matches!(state, REQUESTED | TIMED_OUT)) {
+            libc::syscall(
+                libc::SYS_tgkill,
+                libc::getpid(),
+                libc::gettid(),
+                interrupt_signal(),
+            );
+        }
+        // `long_jump` skips `sigreturn`, thus we must restore the original mask ourselves,
+        // except that the signal is kept blocked for the cleanup of the target scope.
+        let uctx = uctx.cast::<libc::ucontext_t>();
+        let mut mask = (*uctx).uc_sigmask;
+        libc::sigaddset(&raw mut mask, interrupt_signal());
+        libc::pthread_sigmask(libc::SIG_SETMASK, &raw const mask, ptr::null_mut());
+        crate::long_jump_in_signal_handler(JumpPoint::from_raw(n.jp), 0)
+    }
+}
+
+/// Invokes `f` with a node linked, until it returns or a signal handler jumps back.
+///
+/// It must be called with the signal blocked, which is only unblocked during `f`.
+fn catch_signaled<T, F>(shared: &Shared, deadline: Option<Duration>, f: F) -> ControlFlow<usize, T>
+where
+    F: FnOnce() -> T,
+{
+    let prev = HEAD.with(Cell::get);
+    let scope = || {
+        catch_long_jump(|jp| {
+            let node = Node {
+                shared,
+                deadline,
+                jp: jp.as_raw(),
+                prev,
+            };
+            HEAD.with(|head| head.set(&raw const node));
+            compiler_fence(Ordering::SeqCst);
+            if deadline.is_some() {
+                rearm_timer();
+            }
+            set_signal_blocked(false);
+            let ret = f();
+            set_signal_blocked(true);
+            compiler_fence(Ordering::SeqCst);
+            HEAD.with(|head| head.set(prev));
+            ret
+        })
+    };
+
+    // Panics can pass through `catch_long_jump` in this case. We must restore the list.
+    #[cfg(any(feature = "unwind", native_unwind))]
+    let ret = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(scope)) {
+        Ok(ret) => ret,
+        Err(payload) => {
+            set_signal_blocked(true);
+            HEAD.with(|head| head.set(prev));
+            shared.state.store(FINISHED, Ordering::SeqCst);
+            rearm_timer();
+            std::panic::resume_unwind(payload)
+        }
+    };
+    #[cfg(not(any(feature = "unwind", native_unwind)))]
+    let ret = scope();
+
+    // Already unlinked, either by us or by the signal handler. Pending signals are ignored.
+    HEAD.with(|head| head.set(prev));
+    shared.state.store(FINISHED, Ordering::SeqCst);
+    rearm_timer();
+    ret
+}
+
+/// Invokes a closure that can be interrupted by [`InterruptHandle`] from any thread.
+///
+/// This function returns `Ok` if the closure returns normally, or `Err(Interrupted)` if it is
+/// interrupted by [`InterruptHandle::interrupt`]. The handle is passed to the closure, and can be
+/// cloned and sent to other threads. Interrupting a finished closure is a no-op.
+///
+/// It is implemented by sending a signal (see [`interrupt_signal`]) to the current thread, whose
+/// handler [`long_jump`][crate::long_jump]s back to this function. Nesting is supported.
+/// Interrupting an outer scope first jumps out of running inner ones, which complete and then
+/// pass the interruption outwards, thus frames of inner scopes are never skipped.
+///
+/// This function requires cargo feature `interrupt`, and is only supported on Linux.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`], the argument closure must not have a significant `Drop`.
+///
+/// # Errors
+///
+/// Returns [`Interrupted`] if the closure is interrupted.
+///
+/// # Safety
+///
+/// The signal may arrive at any instruction of the closure, including inside its callees.
+/// All stack frames inside the closure must be POFs at any time, and must not hold any lock or
+/// be in the middle of a non-reentrant operation, eg. memory allocation. In practice, the
+/// closure should be a pure computation loop. See [`long_jump`][crate::long_jump] for details.
+///
+/// The closure may also be interrupted right after it returns, in which case the return value
+/// is leaked. So is a panic payload passing through this function, when an outer scope is
+/// interrupted at the same time.
+///
+/// Unlike [`catch_long_jump`], the frame of this function is not a POF. It must not be skipped
+/// by a `long_jump` to an outer checkpoint.
+///
+/// # Panics
+///
+/// Panics if the signal handler cannot be installed.
+///
+/// # Examples
+///
+/// ```
+/// use std::hint::black_box;
+/// use sjlj2::{Interrupted, catch_interruptible};
+///
+/// let ret = unsafe {
+///     catch_interruptible(|handle| {
+///         let handle = handle.clone();
+///         std::thread::spawn(move || handle.interrupt());
+///         loop {
+///             black_box(());
+///         }
+///     })
+/// };
+/// assert_eq!(ret, Err::<(), _>(Interrupted));
+/// ```
+pub unsafe fn catch_interruptible<T, F>(f: F) -> Result<T, Interrupted>
+where
+    F: FnOnce(&InterruptHandle) -> T,
+{
+    install_handler();
+
+    let _block = BlockSignal::new();
+    let handle = InterruptHandle {
+        shared: Arc::new(Shared {
+            pid: unsafe { libc::getpid() },
+            tid: unsafe { libc::gettid() },
+            state: AtomicU8::new(RUNNING),
+        }),
+    };
+    match catch_signaled(&handle.shared, None, || f(&handle)) {
+        ControlFlow::Continue(ret) => Ok(ret),
+        ControlFlow::Break(_) => Err(Interrupted),
+    }
+}
+
+/// Invokes a closure with a wall-clock time limit.
+///
+/// This function returns `Ok` if the closure returns before the timeout, or `Err(TimedOut)` if
+/// it is still running when the time is up. The time is measured on `CLOCK_MONOTONIC`.
+///
+/// It is implemented by arming a per-thread POSIX timer sending a signal (see
+/// [`interrupt_signal`]) to the current thread, whose handler
+/// [`long_jump`][crate::long_jump]s back to this function. The timer is disarmed when the
+/// closure returns. No thread is spawned. Nesting is supported, and it can also be mixed with
+/// [`catch_interruptible`].
+///
+/// This function requires cargo feature `interrupt`, and is only supported on Linux.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`], the argument closure must not have a significant `Drop`.
+///
+/// # Errors
+///
+/// Returns [`TimedOut`] if the closure times out.
+///
+/// # Safety
+///
+/// Same as [`catch_interruptible`].
+///
+/// # Panics
+///
+/// Panics if the signal handler or the timer cannot be set up.
+///
+/// # Examples
+///
+/// ```
+/// use std::hint::black_box;
+/// use std::time::Duration;
+/// use sjlj2::{TimedOut, catch_long_jump_with_timeout};
+///
+/// let ret = unsafe { catch_long_jump_with_timeout(Duration::from_secs(10), || 42) };
+/// assert_eq!(ret, Ok(42));
+///
+/// let ret = unsafe {
+///     catch_long_jump_with_timeout(Duration::from_millis(10), || loop {
+///         black_box(());
+///     })
+/// };
+/// assert_eq!(ret, Err::<(), _>(TimedOut));
+/// ```
+pub unsafe fn catch_long_jump_with_timeout<T, F>(timeout: Duration, f: F) -> Result<T, TimedOut>
+where
+    F: FnOnce() -> T,
+{
+    install_handler();
+
+    let _block = BlockSignal::new();
+    TIMER.with(|_| {});
+
+    let shared = Shared {
+        pid: unsafe { libc::getpid() },
+        tid: unsafe { libc::gettid() },
+        state: AtomicU8::new(RUNNING),
+    };
+    let deadline = now().saturating_add(timeout);
+    match catch_signaled(&shared, Some(deadline), f) {
+        ControlFlow::Continue(ret) => Ok(ret),
+        ControlFlow::Break(_) => Err(TimedOut),
+    }
+}
@@ -54,8 +54,77 @@
 //!
 //! ## Cargo features
 //!
+//! - `std`: Enables `std` support, including thread-local ambient jump scopes
+//!   `with_jump_scope` and `throw`, and their `catch_unwind`-like counterparts
+//!   `catch_long_jump_any` and `throw_any`. Implied by features below requiring `std`.
+//!
 //! - `unwind`: Enables unwinding across [`catch_long_jump`] boundary, by
-//!   catching and resuming the panic payload. This feature requires `std`.
+//!   catching and resuming the panic payload. This feature requires `std`. For `no_std`
+//!   with a custom unwinder, see [`catch_long_jump_with`] instead.
+//!
+//! - `forced-unwind`: Enables `long_jump_unwinding` which runs `Drop`s of skipped frames
+//!   via the platform unwinder. It is only supported on targets using the Itanium C++ ABI
+//!   unwinder, eg. Linux and macOS. It adds an outlined call and a larger buffer to every
+//!   [`catch_long_jump`], even if `long_jump_unwinding` is never used.
+//!
+//! - `fpenv`: Saves the floating-point environment in [`catch_long_jump`] and restores it on
+//!   [`long_jump`], in case skipped frames changed the rounding mode or exception masks. It
+//!   covers MXCSR and x87 control word on x86, FPCR on aarch64, `fcsr` on riscv and FPSCR on
+//!   arm. Exception flags in these registers are also restored. It has no effect on targets
+//!   without FPU or with the portable implementation.
+//!
+//! - `interrupt`: Enables `catch_interruptible` which can be interrupted by other threads,
+//!   and `catch_long_jump_with_timeout`, via signals. It is only supported on Linux.
+//!   This feature requires `std`.
+//!
+//! - `portable`: Enables a fallback implementation on top of `catch_unwind` and
+//!   `resume_unwind` for architectures not listed below. It has no effect on supported
+//!   architectures. This feature requires `std` and `panic=unwind`.
+//!
+//! - `bare-metal`: Saves the interrupt-enable state (`mstatus.MIE`) in [`catch_long_jump`]
+//!   and restores it on [`long_jump`], for jumping out of trap handlers or critical sections
+//!   in machine mode. Interrupts are disabled during the jump. It is only supported on
+//!   riscv32 and riscv64 running in machine mode, since reading `mstatus` traps otherwise.
+//!   Jumping out of `critical_section::with` is also fine with the single-hart implementation
+//!   of the `riscv` crate, which only toggles `mstatus.MIE`, but not with multi-hart ones
+//!   holding a lock. See `examples/bare_metal.rs` for a test running on QEMU.
+//!
+//!   Cortex-M (PRIMASK and BASEPRI) is not supported. The `arm` backend does not support
+//!   Thumb, and leaving a Cortex-M exception handler requires an exception return rather than
+//!   a jump.
+//!
+//! - `diagnostics`: Enables `catch_long_jump_traced` which records the caller location of
+//!   [`long_jump`], and a backtrace with `std`, into the checkpoint. It adds a small cost to
+//!   every `long_jump`.
+//!
+//! - `tracing`: Enters a `tracing` span named `catch_long_jump` for each checkpoint, and
+//!   emits an event on [`long_jump`] with the carried value and caller location, both at
+//!   `DEBUG` level with target `sjlj2`. Spans of checkpoints skipped by a jump are also
+//!   exited. Jumps from the signal handler of `interrupt` emit no event, since it is not
+//!   async-signal-safe. This feature requires `std`.
+//!
+//! - `frame-walk`: Makes [`catch_long_jump`] cheaper by not saving callee-saved registers,
+//!   and makes [`long_jump`] recover them instead by walking frames with the platform unwinder
+//!   (`_Unwind_Backtrace`), which is much slower. It suits code which catches often but jumps
+//!   rarely. All skipped frames must have unwinding information, which Rust emits by default.
+//!   Jumping via [`RawJumpBuf`] from foreign code is not supported. It cannot be combined with
+//!   `interrupt`, since the unwinder is not async-signal-safe. It is only supported on x86\_64
+//!   except Windows.
+//!
+//! - `small`: Optimizes for code size rather than speed, for targets with small flash.
+//!   [`long_jump`] calls a single outlined routine instead of being inlined, and all
+//!   [`catch_long_jump`] share a single type-erased trampoline instead of instantiating one
+//!   per closure, at the cost of an indirect call.
+//!
+//! - `macros`: Enables the `#[pof]` attribute from the companion crate `sjlj2-macros`, which
+//!   statically checks that a function is safe to be skipped by [`long_jump`].
+//!
+//! - `nightly`: Lets panics in [`catch_long_jump`] unwind natively through the assembly,
+//!   instead of being caught and resumed by `unwind`, or aborting without it. This part has
+//!   no effect on Windows, with `panic=abort`, or with the portable implementation.
+//!   With `std`, it also enables `Throws` and `catch_throws`, on which `?` jumps with the
+//!   error instead of returning it. This feature requires a nightly compiler for `asm_unwind`
+//!   and `try_trait_v2`.
 //!
 //! No feature is enabled by default.
 //!
@@ -67,6 +136,14 @@
 //! - riscv32, with or without E-extension
 //! - aarch64 (ARM v8)
 //! - arm
+//! - Any other architectures with feature `portable`, though `long_jump` is way slower
+//!   and executes `Drop`s of skipped frames.
+//!
+//! ## Miri
+//!
+//! Miri does not support inline assembly, thus the portable implementation (see feature
+//! `portable`) is always used under Miri. It checks the code around [`catch_long_jump`] and
+//! reports any [`long_jump`] to a stale [`JumpPoint`] whose closure already returned.
 //!
 //! ## Similar crates
 //!
@@ -83,46 +160,56 @@
 //!   - Slower `long_jump` because of more register restoring.
 //!
 //! [misopt]: https://github.com/rust-lang/rfcs/issues/2625
-#![cfg_attr(not(any(test, feature = "unwind")), no_std)]
+#![cfg_attr(not(any(test, feature = "std", portable)), no_std)]
+#![cfg_attr(native_unwind, feature(asm_unwind))]
+#![cfg_attr(feature = "nightly", feature(try_trait_v2, try_trait_v2_residual))]
 use core::marker::PhantomData;
 use core::mem::{ManuallyDrop, MaybeUninit};
 use core::ops::ControlFlow;
 
-#[cfg(target_arch = "x86_64")]
+#[cfg(all(target_arch = "x86_64", not(portable)))]
 #[macro_use]
 #[path = "./x86_64.rs"]
 mod imp;
 
-#[cfg(all(target_arch = "x86", not(target_env = "msvc")))]
+#[cfg(all(target_arch = "x86", not(target_env = "msvc"), not(portable)))]
 #[macro_use]
 #[path = "./x86.rs"]
 mod imp;
 
-#[cfg(all(target_arch = "x86", target_env = "msvc"))]
+#[cfg(all(target_arch = "x86", target_env = "msvc", not(portable)))]
 #[macro_use]
 #[path = "./x86_msvc.rs"]
 mod imp;
 
-#[cfg(target_arch = "riscv64")]
+#[cfg(all(target_arch = "riscv64", not(portable)))]
 #[macro_use]
 #[path = "./riscv64.rs"]
 mod imp;
 
-#[cfg(target_arch = "riscv32")]
+#[cfg(all(target_arch = "riscv32", not(portable)))]
 #[macro_use]
 #[path = "./riscv32.rs"]
 mod imp;
 
-#[cfg(target_arch = "aarch64")]
+#[cfg(all(target_arch = "aarch64", not(portable)))]
 #[macro_use]
 #[path = "./aarch64.rs"]
 mod imp;
 
-#[cfg(target_arch = "arm")]
+#[cfg(all(target_arch = "arm", not(portable)))]
 #[macro_use]
 #[path = "./arm.rs"]
 mod imp;
 
+#[cfg(portable)]
+#[macro_use]
+#[path = "./portable.rs"]
+mod imp;
+
+#[cfg(all(portable, panic = "abort"))]
+compile_error!("sjlj2: the portable implementation requires `panic=unwind`");
+
 #[cfg(not(any(
     target_arch = "x86_64",
     target_arch = "x86",
@@ -130,10 +217,11 @@ mod imp;
     target_arch = "riscv32",
     target_arch = "aarch64",
     target_arch = "arm",
+    portable,
 )))]
 #[macro_use]
 mod imp {
-    compile_error!("sjlj2: unsupported platform");
+    compile_error!("sjlj2: unsupported platform, consider enabling feature `portable`");
 
     macro_rules! set_jump_raw {
         ($val:tt, $($tt:tt)*) => {
@@ -144,14 +232,216 @@ mod imp {
     pub(crate) unsafe fn long_jump_raw(_buf: *mut (), _data: usize) -> ! {
         unimplemented!()
     }
+
+    pub(crate) unsafe fn long_jump_raw_pair(_buf: *mut (), _data: (usize, usize)) -> ! {
+        unimplemented!()
+    }
+}
+
+/// # Layout stability
+///
+/// The layout of each architecture is part of the public API, and only changes in
+/// semver-incompatible releases. Out-of-crate assembly should use the offset constants below,
+/// and the field offsets of the architecture, rather than hard-coded numbers.
+///
+/// The payload, the stack pointer, the frame pointer, the lander and other fields present
+/// unconditionally are always at the same offsets. Fields after them depend on cargo features,
+/// thus their offsets, [`SIZE`][RawJumpBuf::SIZE] and [`ALIGN`][RawJumpBuf::ALIGN] may change
+/// with them:
+///
+/// - With `fpenv`, the floating-point control fields are appended when there is an FPU.
+/// - With `bare-metal`, `mstatus_mie` is appended on riscv, after those of `fpenv` if any.
+/// - With `frame-walk`, the layout is unchanged, but callee-saved registers are not saved.
+#[cfg(not(portable))]
+pub use imp::RawJumpBuf;
+
+#[cfg(not(portable))]
+impl RawJumpBuf {
+    /// The offset of the payload, which is stored on jumping after all registers are restored.
+    pub const PAYLOAD_OFFSET: usize = 0;
+    /// The offset of the second word of the payload for [`catch_long_jump_pair`], which is
+    /// stored the same way as the first.
+    pub const PAYLOAD_SECOND_OFFSET: usize = size_of::<usize>();
+    /// The offset of the saved stack pointer.
+    pub const SP_OFFSET: usize = core::mem::offset_of!(Self, sp);
+    /// The offset of the saved frame pointer.
+    pub const FP_OFFSET: usize = core::mem::offset_of!(Self, fp);
+    /// The offset of the address to jump to.
+    pub const LANDER_OFFSET: usize = core::mem::offset_of!(Self, lander);
+    /// The size of the buffer.
+    pub const SIZE: usize = size_of::<Self>();
+    /// The alignment of the buffer.
+    pub const ALIGN: usize = align_of::<Self>();
+}
+
+#[cfg(feature = "forced-unwind")]
+#[cfg(any(windows, target_arch = "arm", portable))]
+compile_error!("sjlj2: feature `forced-unwind` is unsupported on this platform");
+
+#[cfg(feature = "forced-unwind")]
+mod forced_unwind;
+
+#[cfg(feature = "forced-unwind")]
+pub use forced_unwind::long_jump_unwinding;
+
+#[cfg(feature = "frame-walk")]
+#[cfg(any(windows, not(target_arch = "x86_64"), portable))]
+compile_error!("sjlj2: feature `frame-walk` is unsupported on this platform");
+
+// `_Unwind_Backtrace` is not async-signal-safe.
+#[cfg(all(feature = "frame-walk", feature = "interrupt"))]
+compile_error!("sjlj2: feature `frame-walk` cannot be used with `interrupt`");
+
+#[cfg(feature = "frame-walk")]
+mod frame_walk;
+
+mod cancel;
+
+pub use cancel::{CancelScope, CancelToken, Cancelled, catch_cancellable};
+
+#[cfg(feature = "bare-metal")]
+#[cfg(any(not(any(target_arch = "riscv32", target_arch = "riscv64")), portable))]
+compile_error!("sjlj2: feature `bare-metal` is unsupported on this platform");
+
+#[cfg(feature = "interrupt")]
+#[cfg(any(not(target_os = "linux"), portable))]
+compile_error!("sjlj2: feature `interrupt` is unsupported on this platform");
+
+#[cfg(feature = "interrupt")]
+mod interrupt;
+
+#[cfg(feature = "interrupt")]
+pub use interrupt::{
+    InterruptHandle, Interrupted, TimedOut, catch_interruptible, catch_long_jump_with_timeout,
+    interrupt_signal,
+};
+
+mod catch_unwind;
+
+#[cfg(feature = "std")]
+pub use catch_unwind::StdUnwind;
+pub use catch_unwind::{CatchUnwind, catch_long_jump_with};
+
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::{JumpInfo, catch_long_jump_traced};
+
+#[cfg(feature = "tracing")]
+mod trace;
+
+mod try_catch;
+
+mod recovery;
+
+pub use recovery::{Recovered, recover, run_with_recovery};
+
+mod scope;
+
+pub use scope::ScopeStack;
+#[cfg(feature = "std")]
+pub use scope::{catch_long_jump_any, throw, throw_any, with_jump_point, with_jump_scope};
+
+#[cfg(feature = "macros")]
+pub use sjlj2_macros::pof;
+
+#[cfg(feature = "macros")]
+#[doc(hidden)]
+pub mod __private {
+    /// Used by `#[pof]` to check a binding.
+    #[inline]
+    pub const fn assert_pof<T: ?Sized>(_: &T) {
+        assert_pof_type::<T>();
+    }
+
+    /// Used by `#[pof]` to check a type.
+    #[inline]
+    pub const fn assert_pof_type<T: ?Sized>() {
+        const {
+            assert!(
+                !core::mem::needs_drop::<T>(),
+                "#[pof] function must not have a local or argument with significant Drop",
+            );
+        }
+    }
 }
 
+#[cfg(all(feature = "nightly", feature = "std"))]
+mod throws;
+
+#[cfg(all(feature = "nightly", feature = "std"))]
+pub use throws::{Throws, catch_throws};
+
+#[cfg(feature = "std")]
+mod future;
+
+#[cfg(feature = "std")]
+pub use future::JumpFuture;
+
 /// A jump checkpoint that you can go back to at any time.
 ///
-/// It consists of a single machine word.
+/// It consists of a single machine word. `P` is the [`Payload`] type carried by
+/// [`long_jump`], which is `usize` for [`catch_long_jump`] and `(usize, usize)` for
+/// [`catch_long_jump_pair`].
 #[doc(alias = "jmp_buf")]
 #[derive(Debug, Clone, Copy)]
-pub struct JumpPoint<'a>(*mut (), PhantomData<fn(&'a ()) -> &'a ()>);
+pub struct JumpPoint<'a, P = usize>(
+    *mut (),
+    PhantomData<fn(&'a ()) -> &'a ()>,
+    PhantomData<fn(P) -> P>,
+);
+
+mod sealed {
+    pub trait Sealed: Copy + core::fmt::Debug {
+        /// Read the payload after jumping.
+        ///
+        /// # Safety
+        ///
+        /// `buf` must be a jump buffer after `long_jump`.
+        unsafe fn read(buf: *const ()) -> Self;
+
+        /// # Safety
+        ///
+        /// See `long_jump`.
+        unsafe fn long_jump_raw(buf: *mut (), data: Self) -> !;
+    }
+}
+
+/// Types of data which [`long_jump`] can carry in registers.
+///
+/// It is implemented for `usize` and `(usize, usize)`, and cannot be implemented outside
+/// this crate.
+pub trait Payload: sealed::Sealed {}
+
+impl sealed::Sealed for usize {
+    unsafe fn read(buf: *const ()) -> Self {
+        unsafe { buf.cast::<usize>().read() }
+    }
+
+    #[cfg_attr(not(feature = "small"), inline)]
+    #[cfg_attr(feature = "small", inline(never))]
+    unsafe fn long_jump_raw(buf: *mut (), data: Self) -> ! {
+        unsafe { imp::long_jump_raw(buf, data) }
+    }
+}
+
+impl Payload for usize {}
+
+impl sealed::Sealed for (usize, usize) {
+    unsafe fn read(buf: *const ()) -> Self {
+        let buf = buf.cast::<usize>();
+        unsafe { (buf.read(), buf.add(1).read()) }
+    }
+
+    #[cfg_attr(not(feature = "small"), inline)]
+    #[cfg_attr(feature = "small", inline(never))]
+    unsafe fn long_jump_raw(buf: *mut (), data: Self) -> ! {
+        unsafe { imp::long_jump_raw_pair(buf, data) }
+    }
+}
+
+impl Payload for (usize, usize) {}
 
 #[cfg(doctest)]
 /// ```compile_fail
@@ -173,7 +463,7 @@ fn _assert_not_or_sync() {}
 /// ```
 fn _assert_invariant() {}
 
-impl JumpPoint<'_> {
+impl<P: Payload> JumpPoint<'_, P> {
     /// Reconstruct from a raw state.
     ///
     /// # Safety
@@ -182,10 +472,14 @@ impl JumpPoint<'_> {
     /// outlive the lifetime of the original [`JumpPoint`] (that is, the argument closure of
     /// [`catch_long_jump`]).
     pub const unsafe fn from_raw(raw: *mut ()) -> Self {
-        Self(raw, PhantomData)
+        Self(raw, PhantomData, PhantomData)
     }
 
     /// Get the underlying raw state.
+    ///
+    /// It points to a [`RawJumpBuf`] except for the portable implementation. Foreign code, eg.
+    /// JIT-compiled code, can jump to it by the instruction sequence described there, except
+    /// with feature `frame-walk`.
     #[must_use]
     pub const fn as_raw(self) -> *mut () {
         self.0
@@ -196,12 +490,26 @@ impl JumpPoint<'_> {
     /// # Safety
     ///
     /// See [`long_jump`].
+    #[track_caller]
     #[inline]
-    pub unsafe fn long_jump(self, data: usize) -> ! {
+    pub unsafe fn long_jump(self, data: P) -> ! {
         unsafe { long_jump(self, data) }
     }
 }
 
+impl JumpPoint<'_> {
+    /// Alias of [`long_jump_unwinding`].
+    ///
+    /// # Safety
+    ///
+    /// See [`long_jump_unwinding`].
+    #[cfg(feature = "forced-unwind")]
+    #[track_caller]
+    pub unsafe fn long_jump_unwinding(self, data: usize) -> ! {
+        unsafe { long_jump_unwinding(self, data) }
+    }
+}
+
 /// Invokes a closure with a jump checkpoint.
 ///
 /// This function returns `Continue` if the closure returns normally. If
@@ -225,8 +533,11 @@ impl JumpPoint<'_> {
 /// # Panics
 ///
 /// It is safe to panic (unwind) in `ordinary` but the behavior varies:
+/// - If cargo feature `nightly` is enabled and supported, panic unwinds through the ASM
+///   boundary natively.
 /// - If cargo feature `unwind` is enabled, panic will be caught, passed through
-///   ASM boundary and resumed.
+///   ASM boundary and resumed. [`catch_long_jump_with`] does the same with a custom
+///   [`CatchUnwind`].
 /// - Otherwise,  it aborts the process.
 ///
 /// Panics from `lander` or `Drop` of `T` are trivial because they are executed
@@ -236,7 +547,8 @@ impl JumpPoint<'_> {
 ///
 /// The stack frame of `catch_long_jump` is a Plain Old Frame (POF), thus nesting
 /// `catch_long_jump` and `long_jump` across multiple levels of
-/// `catch_long_jump` is allowed.
+/// `catch_long_jump` is allowed. With feature `tracing`, it may hold a guard exiting its span,
+/// which is fine to skip, since [`long_jump`] exits spans of checkpoints it skips.
 ///
 /// ```
 /// use std::ops::ControlFlow;
@@ -256,25 +568,144 @@ pub fn catch_long_jump<T, F>(f: F) -> ControlFlow<usize, T>
 where
     F: FnOnce(JumpPoint<'_>) -> T,
 {
-    let mut ret = MaybeUninit::uninit();
+    catch_long_jump_impl(f)
+}
 
-    #[cfg(feature = "unwind")]
+/// Invokes a closure with a jump checkpoint carrying two words.
+///
+/// Same as [`catch_long_jump`], except that [`long_jump`] on the closure argument
+/// [`JumpPoint`] takes a `(usize, usize)`, which is returned in `Break`. Both words are passed
+/// in registers, like the single word in [`catch_long_jump`].
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use sjlj2::catch_long_jump_pair;
+///
+/// let ret = catch_long_jump_pair(|jp| {
+///     unsafe { jp.long_jump((13, 42)) };
+/// });
+/// assert_eq!(ret, ControlFlow::Break((13, 42)));
+/// ```
+#[inline]
+pub fn catch_long_jump_pair<T, F>(f: F) -> ControlFlow<(usize, usize), T>
+where
+    F: FnOnce(JumpPoint<'_, (usize, usize)>) -> T,
+{
+    catch_long_jump_impl(f)
+}
+
+/// The outcome of [`catch_long_jump_outcome`].
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum Outcome<T> {
+    /// The closure returned normally.
+    Returned(T),
+    /// [`long_jump`] was called with the carried value.
+    Jumped(usize),
+    /// The closure panicked with the payload.
+    Panicked(std::boxed::Box<dyn core::any::Any + Send>),
+}
+
+/// Invokes a closure with a jump checkpoint, and also catches panics.
+///
+/// Same as [`catch_long_jump`], except that a panic in the closure is returned as
+/// [`Outcome::Panicked`] instead of being resumed or aborting, so both jumps and panics can be
+/// handled in one place. It does not require feature `unwind`. This function requires
+/// feature `std`.
+///
+/// Since the closure runs inside `catch_unwind`, `long_jump_unwinding` must not be used inside
+/// it, to this or any outer checkpoint.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`], the argument closure must not have a significant `Drop`.
+///
+/// # Examples
+///
+/// ```
+/// use sjlj2::{Outcome, catch_long_jump_outcome};
+///
+/// let ret = catch_long_jump_outcome(|_jp| 1);
+/// assert!(matches!(ret, Outcome::Returned(1)));
+///
+/// let ret = catch_long_jump_outcome(|jp| -> () { unsafe { jp.long_jump(2) } });
+/// assert!(matches!(ret, Outcome::Jumped(2)));
+///
+/// let ret = catch_long_jump_outcome(|_jp| -> () { std::panic::panic_any(3i32) });
+/// let Outcome::Panicked(payload) = ret else { unreachable!() };
+/// assert_eq!(*payload.downcast::<i32>().unwrap(), 3);
+/// ```
+#[cfg(feature = "std")]
+#[inline]
+pub fn catch_long_jump_outcome<T, F>(f: F) -> Outcome<T>
+where
+    F: FnOnce(JumpPoint<'_>) -> T,
+{
+    match catch_long_jump(|jp| {
+        let ret = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(jp)));
+        // Jumps of the portable implementation are also panics. Pass them through.
+        #[cfg(portable)]
+        let ret = match ret {
+            Err(payload) if imp::is_jump(&*payload) => std::panic::resume_unwind(payload),
+            ret => ret,
+        };
+        ret
+    }) {
+        ControlFlow::Continue(Ok(ret)) => Outcome::Returned(ret),
+        ControlFlow::Continue(Err(payload)) => Outcome::Panicked(payload),
+        ControlFlow::Break(val) => Outcome::Jumped(val),
+    }
+}
+
+#[inline]
+fn catch_long_jump_impl<P, T, F>(f: F) -> ControlFlow<P, T>
+where
+    P: Payload,
+    F: FnOnce(JumpPoint<'_, P>) -> T,
+{
+    // The portable implementation already passes through panics, so does `native_unwind`.
+    #[cfg(all(feature = "unwind", not(portable), not(native_unwind)))]
+    {
+        catch_long_jump_unwind::<StdUnwind, _, _, _>(f)
+    }
+
+    #[cfg(any(not(feature = "unwind"), portable, native_unwind))]
+    {
+        catch_long_jump_plain(f)
+    }
+}
+
+#[cfg(not(portable))]
+#[inline]
+fn catch_long_jump_unwind<U, P, T, F>(f: F) -> ControlFlow<P, T>
+where
+    U: CatchUnwind,
+    P: Payload,
+    F: FnOnce(JumpPoint<'_, P>) -> T,
+{
+    let mut ret = MaybeUninit::uninit();
     match set_jump_impl(|jp| {
-        ret.write(std::panic::catch_unwind(std::panic::AssertUnwindSafe(
-            || f(jp),
-        )));
+        ret.write(U::catch_unwind(|| call_ordinary(f, jp)));
     }) {
         // SAFETY: `f` returns normally or caught a panic, thus `ret` is initialized.
         ControlFlow::Continue(()) => match unsafe { ret.assume_init() } {
             Ok(ret) => ControlFlow::Continue(ret),
-            Err(payload) => std::panic::resume_unwind(payload),
+            Err(payload) => U::resume_unwind(payload),
         },
         ControlFlow::Break(val) => ControlFlow::Break(val),
     }
+}
 
-    #[cfg(not(feature = "unwind"))]
+#[cfg(any(not(feature = "unwind"), portable, native_unwind))]
+#[inline]
+fn catch_long_jump_plain<P, T, F>(f: F) -> ControlFlow<P, T>
+where
+    P: Payload,
+    F: FnOnce(JumpPoint<'_, P>) -> T,
+{
+    let mut ret = MaybeUninit::uninit();
     match set_jump_impl(|jp| {
-        ret.write(f(jp));
+        ret.write(call_ordinary(f, jp));
     }) {
         // SAFETY: `f` returns normally, thus `ret` is initialized.
         ControlFlow::Continue(()) => ControlFlow::Continue(unsafe { ret.assume_init() }),
@@ -282,63 +713,173 @@ where
     }
 }
 
+#[cfg(not(feature = "forced-unwind"))]
 #[inline]
-fn set_jump_impl<F>(f: F) -> ControlFlow<usize>
+fn call_ordinary<P, T, F>(f: F, jp: JumpPoint<'_, P>) -> T
 where
-    F: FnOnce(JumpPoint<'_>),
+    F: FnOnce(JumpPoint<'_, P>) -> T,
 {
-    // NB: Properties expected by ASM:
-    // - `jmp_buf` is at offset 0.
-    // - On the exceptional path, the carried value is stored in `jmp_buf[0]`.
-    #[repr(C)]
-    struct Data<F> {
-        jmp_buf: MaybeUninit<imp::Buf>,
-        func: ManuallyDrop<F>,
+    f(jp)
+}
+
+#[cfg(feature = "forced-unwind")]
+use forced_unwind::call_with_boundary as call_ordinary;
+
+// NB: Properties expected by ASM:
+// - `jmp_buf` is at offset 0.
+// - On the exceptional path, the carried value is stored at the beginning of `jmp_buf`.
+// - `long_jump_unwinding` expects its state right after `jmp_buf`.
+// - Fields before `func` do not depend on `F`, so that their offsets are known to `long_jump`.
+#[repr(C)]
+struct Data<F> {
+    jmp_buf: MaybeUninit<imp::Buf>,
+    #[cfg(feature = "forced-unwind")]
+    unwind_state: MaybeUninit<forced_unwind::UnwindState>,
+    #[cfg(feature = "diagnostics")]
+    site: diagnostics::SiteSlot,
+    #[cfg(feature = "std")]
+    heads: scope::Heads,
+    #[cfg(feature = "tracing")]
+    trace: trace::Node,
+    func: ManuallyDrop<F>,
+}
+
+#[inline]
+fn set_jump_impl<P, F>(f: F) -> ControlFlow<P>
+where
+    P: Payload,
+    F: FnOnce(JumpPoint<'_, P>),
+{
+    const {
+        assert!(
+            !core::mem::needs_drop::<F>(),
+            "catch_long_jump closure must not have a significant Drop",
+        );
     }
 
+    #[cfg(not(feature = "small"))]
+    let func = move |raw: *mut ()| f(unsafe { JumpPoint::from_raw(raw) });
+
+    // Erase the closure type, so that all checkpoints share a single `set_jump_data`.
+    #[cfg(feature = "small")]
+    let mut f = ManuallyDrop::new(f);
+    #[cfg(feature = "small")]
+    let func = erase(core::ptr::from_mut(&mut f).cast(), call_erased::<P, F>);
+
+    let mut data = Data {
+        jmp_buf: MaybeUninit::uninit(),
+        #[cfg(feature = "forced-unwind")]
+        unwind_state: MaybeUninit::uninit(),
+        #[cfg(feature = "diagnostics")]
+        site: core::ptr::null_mut(),
+        #[cfg(feature = "std")]
+        heads: scope::Heads::save(),
+        #[cfg(feature = "tracing")]
+        trace: trace::Node::new(),
+        func: ManuallyDrop::new(func),
+    };
+
+    if set_jump_data(&mut data) {
+        // Exit the span entered by `wrap`, and unlink inner checkpoints.
+        #[cfg(feature = "tracing")]
+        unsafe {
+            trace::land(core::ptr::from_mut(&mut data).cast());
+        }
+        // Pop scopes skipped by the jump.
+        #[cfg(feature = "std")]
+        data.heads.restore();
+        // SAFETY: The carried value is stored in `jmp_buf` by `long_jump`.
+        ControlFlow::Break(unsafe { P::read(data.jmp_buf.as_ptr().cast()) })
+    } else {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Call a type-erased `ManuallyDrop<F>` with a raw `JumpPoint`.
+#[cfg(feature = "small")]
+unsafe fn call_erased<P, F>(f: *mut (), raw: *mut ())
+where
+    P: Payload,
+    F: FnOnce(JumpPoint<'_, P>),
+{
+    // SAFETY: `f` points to a `ManuallyDrop<F>`, which is taken only once by `wrap`.
+    unsafe { ManuallyDrop::take(&mut *f.cast::<ManuallyDrop<F>>())(JumpPoint::from_raw(raw)) }
+}
+
+/// Wrap an erased closure into a single non-generic closure type.
+#[cfg(feature = "small")]
+fn erase(f: *mut (), call: unsafe fn(*mut (), *mut ())) -> impl FnOnce(*mut ()) {
+    move |raw| unsafe { call(f, raw) }
+}
+
+/// Invokes `data.func` with a checkpoint in `data.jmp_buf`, returning whether it is jumped to.
+// NB: Why `wrap` is an out-of-line call, rather than inlining the closure between two asm blocks
+// (save, then a register barrier), or using `asm goto` with the lander as a label operand.
+//
+// The compiler only knows the lander is reachable from the asm block that mentions it. If the
+// closure body is inlined after that block, values only live at the lander are dead inside the
+// body, thus their registers and stack slots may be reused there, and a jump from the body
+// lands with them corrupted. Both designs fail `lander_only_live` with optimizations on x86_64.
+// `issue_2625` and `libc_issue_1596` do not catch it, since places captured by the closure are
+// reachable from `data` passed to the asm block, and values used on both paths stay live inside
+// the body. A register barrier after the body does not help, since it does not make values
+// only used by the lander live inside the body. What we need is "may branch to the label from
+// anywhere in this region", ie. LLVM `returns_twice`, which inline assembly cannot express.
+//
+// With the call, the closure is still inlined into `wrap::<F>`, and the caller's frame is opaque
+// to it. The remaining cost on the non-jumping path is the call itself, plus saving
+// callee-saved registers clobbered by `set_jump_raw!`, which any sound design has to pay. The
+// call costs about 1ns of 4.5ns on `sjlj/return` of the bench on x86_64.
+#[cfg_attr(not(feature = "small"), inline)]
+#[cfg_attr(feature = "small", inline(never))]
+fn set_jump_data<F: FnOnce(*mut ())>(data: &mut Data<F>) -> bool {
     macro_rules! gen_wrap {
         ($abi:literal) => {
-            unsafe extern $abi fn wrap<F: FnOnce(JumpPoint<'_>)>(data: &mut Data<F>) {
+            unsafe extern $abi fn wrap<F: FnOnce(*mut ())>(data: &mut Data<F>) {
                 // Non-unwinding ABI generates abort-on-unwind guard since our MSRV >= 1.81.
-                // No need to handle unwinding here.
-                let jp = unsafe { JumpPoint::from_raw(data.jmp_buf.as_mut_ptr().cast()) };
-                unsafe { ManuallyDrop::take(&mut data.func)(jp) };
+                // No need to handle unwinding here. With `native_unwind`, the "-unwind" ABI
+                // is used instead and panics propagate through `set_jump_raw!`.
+                // Derive from the whole `data`, since fields after `jmp_buf` are also accessed
+                // via `JumpPoint`.
+                let data = core::ptr::from_mut(data);
+                // Only exits the span on returning or panicking. Jumps exit it otherwise.
+                #[cfg(feature = "tracing")]
+                let _exit = unsafe { trace::enter(data.cast()) };
+                unsafe { ManuallyDrop::take(&mut (*data).func)(data.cast()) };
             }
         };
     }
 
     // Linux and Windows have different C ABI. Here we choose sysv64 for simplicity.
-    #[cfg(target_arch = "x86_64")]
+    #[cfg(all(target_arch = "x86_64", not(portable), not(native_unwind)))]
     gen_wrap!("sysv64");
+    #[cfg(all(target_arch = "x86_64", native_unwind))]
+    gen_wrap!("sysv64-unwind");
 
     // x86 cdecl pass all arguments on stack, which is inconvenient under the
     // fact that compilers also disagree on stack alignments.
     // Here we choose fastcall to pass through ECX for simplicity.
-    #[cfg(target_arch = "x86")]
+    #[cfg(all(target_arch = "x86", not(portable), not(native_unwind)))]
     gen_wrap!("fastcall");
+    #[cfg(all(target_arch = "x86", native_unwind))]
+    gen_wrap!("fastcall-unwind");
 
-    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86")))]
-    gen_wrap!("C");
-
-    const {
-        assert!(
-            !core::mem::needs_drop::<F>(),
-            "catch_long_jump closure must not have a significant Drop",
-        );
-    }
+    // The portable implementation jumps by unwinding.
+    #[cfg(portable)]
+    gen_wrap!("Rust");
 
-    let mut data = Data::<F> {
-        jmp_buf: MaybeUninit::uninit(),
-        func: ManuallyDrop::new(f),
-    };
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "x86", portable, native_unwind)))]
+    gen_wrap!("C");
+    #[cfg(all(not(any(target_arch = "x86_64", target_arch = "x86")), native_unwind))]
+    gen_wrap!("C-unwind");
 
     unsafe {
-        set_jump_raw!(&raw mut data, wrap::<F>, {
-            let data = unsafe { data.jmp_buf.assume_init().0[0] };
-            return ControlFlow::Break(data);
+        set_jump_raw!(core::ptr::from_mut(data), wrap::<F>, {
+            return true;
         });
-        ControlFlow::Continue(())
     }
+
+    false
 }
 
 /// Long jump to a checkpoint, force unwinding the stack and return an arbitrary
@@ -367,7 +908,27 @@ where
 /// [pof]: https://rust-lang.github.io/rfcs/2945-c-unwind-abi.html#plain-old-frames
 /// [misopt]: https://github.com/rust-lang/rfcs/issues/2625
 #[doc(alias = "longjmp")]
+#[track_caller]
+#[inline]
+pub unsafe fn long_jump<P: Payload>(point: JumpPoint<'_, P>, data: P) -> ! {
+    #[cfg(feature = "diagnostics")]
+    unsafe {
+        diagnostics::record(point.0);
+    }
+    #[cfg(feature = "tracing")]
+    unsafe {
+        trace::jump(point.0, &data);
+    }
+    unsafe { P::long_jump_raw(point.0, data) }
+}
+
+/// Same as [`long_jump`], but without recording for `diagnostics` or `tracing`, which are not
+/// async-signal-safe.
+#[cfg(feature = "interrupt")]
 #[inline]
-pub unsafe fn long_jump(point: JumpPoint<'_>, data: usize) -> ! {
-    unsafe { imp::long_jump_raw(point.0, data) }
+pub(crate) unsafe fn long_jump_in_signal_handler<P: Payload>(
+    point: JumpPoint<'_, P>,
+    data: P,
+) -> ! {
+    unsafe { P::long_jump_raw(point.0, data) }
 }
@@ -0,0 +1,63 @@
+//! Portable fallback for architectures without an assembly implementation.
+//!
+//! `long_jump` is emulated by `resume_unwind` with a private payload, which is caught by
+//! `catch_unwind` in `set_jump_raw!` of the target checkpoint. It is much slower and runs `Drop`s
+//! of skipped frames, but it is always correct as long as unwinding is available.
+use std::any::Any;
+use std::boxed::Box;
+use std::panic::resume_unwind;
+
+#[repr(transparent)]
+pub(crate) struct Buf(pub [usize; 2]);
+
+/// The private unwinding payload of a `long_jump`, carrying the target `jmp_buf`.
+struct Jump(*mut ());
+
+// SAFETY: It is only a marker. The pointer is never dereferenced in other threads.
+unsafe impl Send for Jump {}
+
+macro_rules! set_jump_raw {
+    ($buf_ptr:expr, $func:path, $lander:block) => {
+        // `wrap` uses the Rust ABI in this case, thus unwinding through it is allowed.
+        let buf_ptr = $buf_ptr;
+        if let Err(payload) =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| $func(&mut *buf_ptr)))
+        {
+            crate::imp::catch_jump(payload, buf_ptr.cast());
+            // The lander is written for asm labels, which is not an unsafe context.
+            #[allow(unused_unsafe)]
+            $lander
+        }
+    };
+}
+
+/// Handle a caught unwinding. Return if it is a `long_jump` to `buf`, or continue unwinding.
+pub(crate) fn catch_jump(payload: Box<dyn Any + Send>, buf: *mut ()) {
+    match payload.downcast::<Jump>() {
+        Ok(jump) if jump.0 == buf => {}
+        Ok(jump) => resume_unwind(jump),
+        Err(payload) => resume_unwind(payload),
+    }
+}
+
+/// Returns whether a caught unwinding is a `long_jump`.
+pub(crate) fn is_jump(payload: &(dyn Any + Send)) -> bool {
+    payload.is::<Jump>()
+}
+
+#[inline]
+pub(crate) unsafe fn long_jump_raw(buf: *mut (), data: usize) -> ! {
+    // Store the data like native implementations. This also let Miri catch jumps to a dead
+    // checkpoint, since `jmp_buf` is deallocated when its closure returns.
+    unsafe { buf.cast::<usize>().write(data) };
+    resume_unwind(Box::new(Jump(buf)))
+}
+
+#[inline]
+pub(crate) unsafe fn long_jump_raw_pair(buf: *mut (), data: (usize, usize)) -> ! {
+    unsafe {
+        buf.cast::<usize>().write(data.0);
+        buf.cast::<usize>().add(1).write(data.1);
+    }
+    resume_unwind(Box::new(Jump(buf)))
+}
@@ -0,0 +1,105 @@
+use core::fmt;
+use core::ops::ControlFlow;
+use core::panic::{Location, PanicInfo};
+
+use crate::ScopeStack;
+
+// SAFETY: Callers of `run_with_recovery` and `recover` guarantee single-threaded access.
+static RECOVERY: ScopeStack = unsafe { ScopeStack::new() };
+
+/// The error returned by [`run_with_recovery`] when a panic is recovered by [`recover`].
+#[derive(Debug, Clone, Copy)]
+pub struct Recovered {
+    location: Option<&'static Location<'static>>,
+}
+
+impl Recovered {
+    /// Get the location of the recovered panic, if available.
+    #[must_use]
+    pub fn location(&self) -> Option<&'static Location<'static>> {
+        self.location
+    }
+}
+
+impl fmt::Display for Recovered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some(loc) => write!(f, "recovered from panic at {loc}"),
+            None => f.write_str("recovered from panic"),
+        }
+    }
+}
+
+impl core::error::Error for Recovered {}
+
+/// Invokes a closure as a recovery point for [`recover`] in the `#[panic_handler]`.
+///
+/// This returns `Ok` if the closure returns normally, or `Err` if it panics and the panic
+/// handler calls [`recover`], which jumps back here. It is mainly for `no_std` firmware with
+/// `panic=abort` to log and restart its main loop, instead of resetting. Nesting is allowed,
+/// and the innermost one is recovered to.
+///
+/// See `examples/codegen.rs` for usage in a `no_std` binary.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`][crate::catch_long_jump], the argument closure must not have a
+/// significant `Drop`.
+///
+/// # Safety
+///
+/// It uses a global state without synchronization. It must only be called by a single thread,
+/// and not inside interrupt handlers.
+///
+/// # Errors
+///
+/// Returns [`Recovered`] if the closure panicked and is recovered by [`recover`].
+#[inline]
+pub unsafe fn run_with_recovery<T, F>(f: F) -> Result<T, Recovered>
+where
+    F: FnOnce() -> T,
+{
+    match RECOVERY.with_jump_scope(f) {
+        ControlFlow::Continue(ret) => Ok(ret),
+        // SAFETY: The data is the location pointer from `recover`, or null.
+        ControlFlow::Break(data) => Err(Recovered {
+            location: unsafe { (data as *const Location<'static>).as_ref() },
+        }),
+    }
+}
+
+/// Long jump to the innermost [`run_with_recovery`] from a `#[panic_handler]`.
+///
+/// This returns if there is no active [`run_with_recovery`], so that the panic handler can fall
+/// back, eg. to reset.
+///
+/// ```ignore
+/// #[panic_handler]
+/// fn panic_handler(info: &core::panic::PanicInfo<'_>) -> ! {
+///     // SAFETY: See below.
+///     unsafe { sjlj2::recover(info) };
+///     loop {}
+/// }
+/// ```
+///
+/// # Safety
+///
+/// It must be called from the `#[panic_handler]` with its argument, on the same thread as
+/// [`run_with_recovery`].
+///
+/// All stack frames between the panic site and the corresponding [`run_with_recovery`] are
+/// deallocated without running `Drop`. Same as [`long_jump`][crate::long_jump], they must be
+/// POFs. In particular, the panic must not happen inside interrupt handlers.
+#[track_caller]
+#[inline]
+pub unsafe fn recover(info: &PanicInfo<'_>) {
+    if !RECOVERY.is_in_scope() {
+        return;
+    }
+    // Locations of panics are always from `Location::caller`, which are `'static`.
+    let location = info.location().map_or(core::ptr::null(), |loc| {
+        core::ptr::from_ref(loc).cast::<()>()
+    });
+    // SAFETY: There is an active scope, and frames between are POFs as guaranteed by the caller.
+    unsafe { RECOVERY.throw(location as usize) }
+}
@@ -1,20 +1,85 @@
-// s0, s1, sp, lander
-#[repr(transparent)]
-pub(crate) struct Buf(pub [usize; 4]);
+/// The jump buffer layout on riscv64.
+///
+/// A jump is performed as follows, with `a0` pointing to the buffer and `a1` holding the
+/// payload. With feature `fpenv`, `fcsr` is restored first. With feature `bare-metal`,
+/// `csrci mstatus, 8` is executed first to disable interrupts, and `csrs mstatus` with
+/// `mstatus_mie` right before the final `jalr`.
+///
+/// ```text
+/// ld s0,  0(a0)
+/// sd a1,  0(a0)
+/// ld s1,  8(a0)
+/// ld sp, 16(a0)
+/// ld a2, 24(a0)
+/// jalr x0, a2
+/// ```
+#[repr(C)]
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct RawJumpBuf {
+    /// `s0` (`fp`), and also the payload after jumping.
+    pub fp: usize,
+    /// `s1`.
+    pub s1: usize,
+    /// `sp`.
+    pub sp: usize,
+    /// The address to jump to.
+    pub lander: usize,
+    /// `fcsr`, only with feature `fpenv` and F-extension enabled.
+    #[cfg(fpenv)]
+    pub fcsr: u32,
+    /// The MIE bit of `mstatus`, only with feature `bare-metal`.
+    #[cfg(feature = "bare-metal")]
+    pub mstatus_mie: usize,
+}
+
+pub(crate) type Buf = RawJumpBuf;
 
 macro_rules! set_jump_raw {
     ($buf_ptr:expr, $func:path, $lander:block) => {
+        #[cfg(native_unwind)]
+        let jumped: usize;
         core::arch::asm!(
+            #[cfg(not(native_unwind))]
             "la a1, {lander}",
+            #[cfg(native_unwind)]
+            "la a1, 3f",
             "sd s0,   (a0)",
             "sd s1,  8(a0)",
             "sd sp, 16(a0)",
             "sd a1, 24(a0)",
+            #[cfg(fpenv)]
+            "csrr a1, fcsr",
+            #[cfg(fpenv)]
+            "sw a1, 32(a0)",
+            #[cfg(feature = "bare-metal")]
+            "csrr a1, mstatus",
+            #[cfg(feature = "bare-metal")]
+            "andi a1, a1, 8",
+            #[cfg(feature = "bare-metal")]
+            "sd a1, {mie}(a0)",
             "call {func}",
+            #[cfg(native_unwind)]
+            "li a1, 0",
+            #[cfg(native_unwind)]
+            "j 4f",
+            #[cfg(native_unwind)]
+            "3:",
+            #[cfg(native_unwind)]
+            "li a1, 1",
+            #[cfg(native_unwind)]
+            "4:",
 
             in("a0") $buf_ptr, // arg0
             func = sym $func,
+            #[cfg(feature = "bare-metal")]
+            mie = const core::mem::offset_of!(crate::imp::RawJumpBuf, mstatus_mie),
+            #[cfg(not(native_unwind))]
             lander = label $lander,
+            #[cfg(native_unwind)]
+            lateout("a1") jumped,
+            #[cfg(native_unwind)]
+            options(may_unwind),
 
             // Callee saved registers.
             lateout("ra") _,
@@ -45,7 +110,10 @@ macro_rules! set_jump_raw {
             lateout("fs11") _,
             // Caller saved registers.
             clobber_abi("C"),
-        )
+        );
+        #[cfg(native_unwind)]
+        #[allow(unused_unsafe)]
+        if jumped != 0 $lander
     };
 }
 
@@ -58,19 +126,72 @@ pub(crate) unsafe fn long_jump_raw(jp: *mut (), data: usize) -> ! {
             #[cfg(emit_cfi)]
             ".cfi_undefined ra",
 
+            #[cfg(feature = "bare-metal")]
+            "csrci mstatus, 8",
+            #[cfg(fpenv)]
+            "lw a2, 32(a0)",
+            #[cfg(fpenv)]
+            "csrw fcsr, a2",
             "ld s0,   (a0)",
             "sd a1,   (a0)",
             "ld s1,  8(a0)",
             "ld sp, 16(a0)",
             "ld a2, 24(a0)",
+            #[cfg(feature = "bare-metal")]
+            "ld a4, {mie}(a0)",
+            #[cfg(feature = "bare-metal")]
+            "csrs mstatus, a4",
             "jalr x0, a2",
 
             #[cfg(emit_cfi)]
             ".cfi_restore_state",
 
             in("a0") jp,
+            #[cfg(feature = "bare-metal")]
+            mie = const core::mem::offset_of!(RawJumpBuf, mstatus_mie),
             in("a1") data,
             options(noreturn, nostack),
         )
     }
 }
+
+/// Same as `long_jump_raw`, but store two words of payload.
+#[inline]
+pub(crate) unsafe fn long_jump_raw_pair(jp: *mut (), data: (usize, usize)) -> ! {
+    unsafe {
+        core::arch::asm!(
+            #[cfg(emit_cfi)]
+            ".cfi_remember_state",
+            #[cfg(emit_cfi)]
+            ".cfi_undefined ra",
+
+            #[cfg(feature = "bare-metal")]
+            "csrci mstatus, 8",
+            #[cfg(fpenv)]
+            "lw a2, 32(a0)",
+            #[cfg(fpenv)]
+            "csrw fcsr, a2",
+            "ld s0,   (a0)",
+            "sd a1,   (a0)",
+            "ld s1,  8(a0)",
+            "ld sp, 16(a0)",
+            "sd a3,  8(a0)",
+            "ld a2, 24(a0)",
+            #[cfg(feature = "bare-metal")]
+            "ld a4, {mie}(a0)",
+            #[cfg(feature = "bare-metal")]
+            "csrs mstatus, a4",
+            "jalr x0, a2",
+
+            #[cfg(emit_cfi)]
+            ".cfi_restore_state",
+
+            in("a0") jp,
+            #[cfg(feature = "bare-metal")]
+            mie = const core::mem::offset_of!(RawJumpBuf, mstatus_mie),
+            in("a1") data.0,
+            in("a3") data.1,
+            options(noreturn, nostack),
+        )
+    }
+}
@@ -0,0 +1,385 @@
+use core::cell::Cell;
+use core::ops::ControlFlow;
+use core::ptr;
+
+use crate::{JumpPoint, catch_long_jump};
+
+/// An intrusive stack of jump scopes, to jump to the innermost one without passing [`JumpPoint`]
+/// all the way down.
+///
+/// This is similar to `lua_error` and `lua_pcall` of Lua. Each scope only keeps the
+/// previous head in its own stack frame, thus no allocation is needed.
+///
+/// With feature `std`, a thread-local instance is available via `with_jump_scope` and
+/// `throw`. For `no_std` environments, a `static` instance can be used instead.
+///
+/// Scopes of the thread-local instances are also popped when jumping to any outer checkpoint,
+/// since every [`catch_long_jump`][crate::catch_long_jump] restores them. This is not possible
+/// for other instances, whose scope frames are thus not POFs: a jump must never skip them,
+/// except to an outer scope of the same instance.
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use sjlj2::ScopeStack;
+///
+/// // SAFETY: It is only accessed by this thread.
+/// static SCOPES: ScopeStack = unsafe { ScopeStack::new() };
+///
+/// fn builtin(x: usize) -> usize {
+///     if x == 0 {
+///         // SAFETY: All frames between the scope and here are POFs.
+///         unsafe { SCOPES.throw(42) };
+///     }
+///     x
+/// }
+///
+/// assert_eq!(SCOPES.with_jump_scope(|| builtin(1)), ControlFlow::Continue(1));
+/// assert_eq!(SCOPES.with_jump_scope(|| builtin(0)), ControlFlow::Break(42));
+/// ```
+#[derive(Debug)]
+pub struct ScopeStack {
+    head: Cell<*mut ()>,
+}
+
+// SAFETY: It can only be constructed by the unsafe `ScopeStack::new`, whose caller guarantees
+// that it is never accessed from multiple threads.
+unsafe impl Sync for ScopeStack {}
+
+impl ScopeStack {
+    /// Create an empty scope stack.
+    ///
+    /// # Safety
+    ///
+    /// The returned value must only be accessed by a single thread, despite it being `Sync`.
+    /// This is to allow putting it in a `static` for `no_std` single-threaded environments.
+    #[must_use]
+    pub const unsafe fn new() -> Self {
+        Self {
+            head: Cell::new(ptr::null_mut()),
+        }
+    }
+
+    /// Returns whether there is any active scope.
+    #[must_use]
+    pub fn is_in_scope(&self) -> bool {
+        !self.head.get().is_null()
+    }
+
+    /// Invokes a closure with a new innermost jump scope pushed.
+    ///
+    /// This returns `Continue` if the closure returns normally, or `Break` if [`ScopeStack::throw`]
+    /// is called when this is the innermost scope. The scope is popped in either case, or when
+    /// jumping to an outer scope. See [`ScopeStack`] for jumping to other outer checkpoints.
+    ///
+    /// # Precondition
+    ///
+    /// Same as [`catch_long_jump`][crate::catch_long_jump], the argument closure must not have a
+    /// significant `Drop`.
+    #[inline]
+    pub fn with_jump_scope<T, F>(&self, f: F) -> ControlFlow<usize, T>
+    where
+        F: FnOnce() -> T,
+    {
+        let prev = self.head.get();
+        let scope = || {
+            catch_long_jump(|jp| {
+                self.head.set(jp.as_raw());
+                f()
+            })
+        };
+
+        // Panics can pass through `catch_long_jump` in these cases. We must restore the stack.
+        // It is not a `Drop` guard, so this frame is still POF for jumps to outer scopes.
+        // Without `std`, panics cannot be caught anyway.
+        #[cfg(any(feature = "unwind", portable, all(native_unwind, feature = "std")))]
+        let ret = match std::panic::catch_unwind(std::panic::AssertUnwindSafe(scope)) {
+            Ok(ret) => ret,
+            Err(payload) => {
+                self.head.set(prev);
+                std::panic::resume_unwind(payload)
+            }
+        };
+        #[cfg(not(any(feature = "unwind", portable, all(native_unwind, feature = "std"))))]
+        let ret = scope();
+
+        self.head.set(prev);
+        ret
+    }
+
+    /// Invokes a closure with the [`JumpPoint`] of the innermost scope, or `None` if there is no
+    /// active scope.
+    ///
+    /// The jump point cannot escape the closure, since the innermost scope may be popped
+    /// afterwards.
+    #[inline]
+    pub fn with_jump_point<R, F>(&self, f: F) -> R
+    where
+        F: for<'a> FnOnce(Option<JumpPoint<'a>>) -> R,
+    {
+        let head = self.head.get();
+        // SAFETY: The head is only set by an active scope, which outlives the closure.
+        f((!head.is_null()).then(|| unsafe { JumpPoint::from_raw(head) }))
+    }
+
+    /// Long jump to the innermost scope of this stack, with an arbitrary `data`.
+    ///
+    /// # Safety
+    ///
+    /// All stack frames between the current and the innermost [`ScopeStack::with_jump_scope`]
+    /// must be POFs. See [`long_jump`][crate::long_jump] for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no active scope.
+    #[track_caller]
+    #[inline]
+    pub unsafe fn throw(&self, data: usize) -> ! {
+        let head = self.head.get();
+        assert!(!head.is_null(), "no active jump scope");
+        // SAFETY: The head is only set by an active scope.
+        unsafe { JumpPoint::from_raw(head).long_jump(data) }
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static SCOPES: ScopeStack = const {
+        // SAFETY: Thread-locals are only accessed by their owner threads.
+        unsafe { ScopeStack::new() }
+    };
+}
+
+/// Heads of the thread-local scope stacks.
+///
+/// Every checkpoint saves them and restores them when it is jumped to, so that scopes skipped by
+/// a jump to a checkpoint other than a scope, eg. of [`catch_long_jump`][crate::catch_long_jump]
+/// or [`CancelScope::check`][crate::CancelScope::check], are popped too.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy)]
+pub(crate) struct Heads {
+    scopes: *mut (),
+    any_scopes: *mut (),
+    #[cfg(feature = "nightly")]
+    throws: *mut (),
+}
+
+#[cfg(feature = "std")]
+impl Heads {
+    #[inline]
+    pub(crate) fn save() -> Self {
+        Self {
+            scopes: SCOPES.with(|s| s.head.get()),
+            any_scopes: ANY_SCOPES.with(|s| s.head.get()),
+            #[cfg(feature = "nightly")]
+            throws: crate::throws::SCOPES.with(|s| s.head.get()),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn restore(self) {
+        SCOPES.with(|s| s.head.set(self.scopes));
+        ANY_SCOPES.with(|s| s.head.set(self.any_scopes));
+        #[cfg(feature = "nightly")]
+        crate::throws::SCOPES.with(|s| s.head.set(self.throws));
+    }
+}
+
+/// Invokes a closure with a new innermost jump scope pushed onto the thread-local
+/// [`ScopeStack`].
+///
+/// See [`ScopeStack::with_jump_scope`] for details. This function requires feature `std`.
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use sjlj2::{throw, with_jump_scope};
+///
+/// let ret = with_jump_scope(|| {
+///     let inner = with_jump_scope(|| unsafe { throw(1) });
+///     assert_eq!(inner, ControlFlow::<_, ()>::Break(1));
+///     unsafe { throw(2) }
+/// });
+/// assert_eq!(ret, ControlFlow::<_, ()>::Break(2));
+/// ```
+#[cfg(feature = "std")]
+#[inline]
+pub fn with_jump_scope<T, F>(f: F) -> ControlFlow<usize, T>
+where
+    F: FnOnce() -> T,
+{
+    SCOPES.with(|scopes| scopes.with_jump_scope(f))
+}
+
+/// Long jump to the innermost scope of the thread-local [`ScopeStack`].
+///
+/// See [`ScopeStack::throw`] for details. This function requires feature `std`.
+///
+/// # Safety
+///
+/// All stack frames between the current and the innermost [`with_jump_scope`] must be POFs.
+/// See [`long_jump`][crate::long_jump] for details.
+///
+/// # Panics
+///
+/// Panics if there is no active scope on the current thread.
+#[cfg(feature = "std")]
+#[track_caller]
+#[inline]
+pub unsafe fn throw(data: usize) -> ! {
+    let head = SCOPES.with(|scopes| scopes.head.get());
+    assert!(!head.is_null(), "no active jump scope");
+    // SAFETY: The head is only set by an active scope.
+    unsafe { JumpPoint::from_raw(head).long_jump(data) }
+}
+
+/// Invokes a closure with the [`JumpPoint`] of the innermost scope of the thread-local
+/// [`ScopeStack`], or `None` if there is no active scope.
+///
+/// See [`ScopeStack::with_jump_point`] for details. This function requires feature `std`.
+///
+/// ```
+/// use std::ops::ControlFlow;
+/// use sjlj2::{with_jump_point, with_jump_scope};
+///
+/// assert!(with_jump_point(|jp| jp.is_none()));
+/// let ret = with_jump_scope(|| {
+///     with_jump_point(|jp| {
+///         // SAFETY: There are only POFs between.
+///         unsafe { jp.unwrap().long_jump(42) }
+///     })
+/// });
+/// assert_eq!(ret, ControlFlow::<_, ()>::Break(42));
+/// ```
+///
+/// The jump point cannot escape the closure.
+///
+/// ```compile_fail
+/// let jp = sjlj2::with_jump_point(|jp| jp);
+/// ```
+#[cfg(feature = "std")]
+#[inline]
+pub fn with_jump_point<R, F>(f: F) -> R
+where
+    F: for<'a> FnOnce(Option<JumpPoint<'a>>) -> R,
+{
+    SCOPES.with(|scopes| scopes.with_jump_point(f))
+}
+
+// Scopes of `catch_long_jump_any`.
+#[cfg(feature = "std")]
+std::thread_local! {
+    static ANY_SCOPES: ScopeStack = const {
+        // SAFETY: Thread-locals are only accessed by their owner threads.
+        unsafe { ScopeStack::new() }
+    };
+}
+
+// The payload of `throw_any` being jumped with, taken by the scope it lands on. It is passed
+// aside rather than carried by `long_jump`, since a pointer cast to `usize` loses its
+// provenance.
+#[cfg(feature = "std")]
+std::thread_local! {
+    static THROWN: Cell<Option<Box<dyn core::any::Any + Send>>> = const { Cell::new(None) };
+}
+
+/// Invokes a closure, capturing the payload of [`throw_any`] if one occurs.
+///
+/// This is a drop-in replacement for [`std::panic::catch_unwind`] when the closure exits via
+/// [`throw_any`] instead of panicking. It returns `Ok` if the closure returns normally, or `Err`
+/// with the payload of `throw_any` called when this is the innermost such scope.
+///
+/// Panics are not caught and pass through. Scopes of [`with_jump_scope`] and this function are
+/// independent. This function requires feature `std`.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`][crate::catch_long_jump], the argument closure must not have a
+/// significant `Drop`. It may be exited by other jumps, eg. [`throw`], which pop its scope as
+/// well.
+///
+/// # Errors
+///
+/// Returns the payload of [`throw_any`] if the closure throws.
+///
+/// # Examples
+///
+/// ```
+/// use sjlj2::{catch_long_jump_any, throw_any};
+///
+/// let ret = catch_long_jump_any(|| -> i32 {
+///     // SAFETY: There is no frame between.
+///     unsafe { throw_any("oops") }
+/// });
+/// assert_eq!(*ret.unwrap_err().downcast::<&str>().unwrap(), "oops");
+/// ```
+#[cfg(feature = "std")]
+#[inline]
+pub fn catch_long_jump_any<T, F>(f: F) -> Result<T, Box<dyn core::any::Any + Send>>
+where
+    F: FnOnce() -> T,
+{
+    ANY_SCOPES.with(|scopes| scopes.catch_any(f))
+}
+
+#[cfg(feature = "std")]
+impl ScopeStack {
+    /// Same as [`ScopeStack::with_jump_scope`], capturing the payload of [`throw_any_to`]. All
+    /// scopes of the stack must be pushed by this.
+    #[inline]
+    pub(crate) fn catch_any<T, F>(&self, f: F) -> Result<T, Box<dyn core::any::Any + Send>>
+    where
+        F: FnOnce() -> T,
+    {
+        match self.with_jump_scope(f) {
+            ControlFlow::Continue(ret) => Ok(ret),
+            ControlFlow::Break(_) => Err(THROWN
+                .take()
+                .expect("scopes of catch_any must only be jumped to by throw_any")),
+        }
+    }
+
+    /// Get the innermost scope, or null if there is none.
+    pub(crate) fn head(&self) -> *mut () {
+        self.head.get()
+    }
+}
+
+/// Long jump to the scope `head` of [`ScopeStack::catch_any`] with a boxed `payload`, or pass it
+/// to [`std::panic::resume_unwind`] if `head` is null.
+///
+/// `head` should be read inside `LocalKey::with` and passed out, since jumping from its closure
+/// would lose the caller location.
+///
+/// # Safety
+///
+/// `head` must be the innermost scope of a stack, and all stack frames between the current and
+/// it must be POFs.
+#[cfg(feature = "std")]
+#[track_caller]
+#[inline]
+pub(crate) unsafe fn throw_any_to(head: *mut (), payload: Box<dyn core::any::Any + Send>) -> ! {
+    if head.is_null() {
+        std::panic::resume_unwind(payload);
+    }
+    THROWN.set(Some(payload));
+    // SAFETY: The head is only set by an active scope of `catch_any`.
+    unsafe { JumpPoint::<usize>::from_raw(head).long_jump(0) }
+}
+
+/// Long jump to the innermost [`catch_long_jump_any`] with a boxed `payload`.
+///
+/// This mirrors [`std::panic::panic_any`]. If there is no active scope on the current thread,
+/// the payload is passed to [`std::panic::resume_unwind`] instead. This function requires
+/// feature `std`.
+///
+/// # Safety
+///
+/// All stack frames between the current and the innermost [`catch_long_jump_any`] must be POFs.
+/// See [`long_jump`][crate::long_jump] for details.
+#[cfg(feature = "std")]
+#[track_caller]
+#[inline]
+pub unsafe fn throw_any<M: core::any::Any + Send>(payload: M) -> ! {
+    let head = ANY_SCOPES.with(ScopeStack::head);
+    // SAFETY: Frames between are POFs, as guaranteed by the caller.
+    unsafe { throw_any_to(head, Box::new(payload)) }
+}
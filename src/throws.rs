@@ -0,0 +1,141 @@
+use core::any::Any;
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use core::ops::{ControlFlow, FromResidual, Residual, Try};
+
+use std::boxed::Box;
+
+use crate::ScopeStack;
+use crate::scope::throw_any_to;
+
+// Scopes of `catch_throws`. It is separated from `catch_long_jump_any`, since `?` jumps from safe
+// code, which is only sound to scopes whose callers guarantee that all frames between are POFs.
+std::thread_local! {
+    pub(crate) static SCOPES: ScopeStack = const {
+        // SAFETY: Thread-locals are only accessed by their owner threads.
+        unsafe { ScopeStack::new() }
+    };
+}
+
+/// A return type on which `?` jumps with the error, instead of returning it.
+///
+/// Inside a function returning `Throws<T, E>`, `expr?` on a `Result<_, F>` with `E: From<F>`
+/// jumps to the innermost [`catch_throws`] with the converted error. `expr?` on another
+/// `Throws` simply unwraps it, since it never carries an error. Thus a chain of helpers
+/// returning `Result` can be converted by only changing their return types, and wrapping the
+/// final value by [`Throws::new`].
+///
+/// Scopes of [`catch_throws`] are independent of
+/// [`catch_long_jump_any`][crate::catch_long_jump_any] and [`throw_any`][crate::throw_any]. If
+/// there is no active scope, the error is passed to [`std::panic::resume_unwind`] instead. This
+/// type requires features `nightly` and `std`.
+///
+/// # Examples
+///
+/// ```
+/// use sjlj2::{Throws, catch_throws};
+///
+/// fn parse(s: &str) -> Throws<u32, std::num::ParseIntError> {
+///     Throws::new(s.parse::<u32>()?)
+/// }
+///
+/// fn sum(a: &str, b: &str) -> Throws<u32, std::num::ParseIntError> {
+///     Throws::new(parse(a)? + parse(b)?)
+/// }
+///
+/// // SAFETY: All frames between are POFs.
+/// let ret = unsafe { catch_throws(|| sum("1", "2")) };
+/// assert_eq!(ret, Ok(3));
+/// let ret = unsafe { catch_throws(|| sum("1", "x")) };
+/// assert!(ret.is_err());
+/// ```
+#[derive(Debug)]
+#[must_use]
+pub struct Throws<T, E>(T, PhantomData<fn() -> E>);
+
+impl<T, E> Throws<T, E> {
+    /// Wrap a value.
+    pub const fn new(value: T) -> Self {
+        Self(value, PhantomData)
+    }
+
+    /// Unwrap the value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, E> Try for Throws<T, E> {
+    type Output = T;
+    type Residual = Throws<Infallible, E>;
+
+    #[inline]
+    fn from_output(output: T) -> Self {
+        Self::new(output)
+    }
+
+    #[inline]
+    fn branch(self) -> ControlFlow<Self::Residual, T> {
+        ControlFlow::Continue(self.0)
+    }
+}
+
+impl<T, E> Residual<T> for Throws<Infallible, E> {
+    type TryType = Throws<T, E>;
+}
+
+impl<T, E> FromResidual<Throws<Infallible, E>> for Throws<T, E> {
+    fn from_residual(residual: Throws<Infallible, E>) -> Self {
+        match residual.0 {}
+    }
+}
+
+impl<T, E, F> FromResidual<Result<Infallible, F>> for Throws<T, E>
+where
+    E: From<F> + Any + Send,
+{
+    #[track_caller]
+    #[inline]
+    fn from_residual(residual: Result<Infallible, F>) -> Self {
+        let Err(err) = residual;
+        let payload: Box<dyn Any + Send> = Box::new(E::from(err));
+        let head = SCOPES.with(ScopeStack::head);
+        // SAFETY: Only `catch_throws` pushes scopes, whose callers guarantee that frames between
+        // are POFs.
+        unsafe { throw_any_to(head, payload) }
+    }
+}
+
+/// Invokes a closure returning [`Throws`], capturing the error of `?` if one occurs.
+///
+/// It returns `Ok` if the closure returns normally, or `Err` if `?` on a `Result` jumps with
+/// an error of type `E`. Errors of other types from an inner `?` without a matching scope are
+/// passed to [`std::panic::resume_unwind`]. This function requires features `nightly` and `std`.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`][crate::catch_long_jump], the argument closure must not have a
+/// significant `Drop`.
+///
+/// # Safety
+///
+/// All stack frames between this function and any `?` on `Result` in functions returning
+/// [`Throws`] must be POFs. See [`long_jump`][crate::long_jump] for details.
+///
+/// # Errors
+///
+/// Returns the error of `?` if the closure jumps.
+#[inline]
+pub unsafe fn catch_throws<T, E, F>(f: F) -> Result<T, E>
+where
+    E: Any + Send,
+    F: FnOnce() -> Throws<T, E>,
+{
+    match SCOPES.with(|scopes| scopes.catch_any(f)) {
+        Ok(ret) => Ok(ret.0),
+        Err(payload) => match payload.downcast::<E>() {
+            Ok(err) => Err(*err),
+            Err(payload) => std::panic::resume_unwind(payload),
+        },
+    }
+}
@@ -0,0 +1,120 @@
+//! `tracing` integration.
+//!
+//! Each checkpoint enters a span, if enabled, and links it into a thread-local intrusive list
+//! through a [`Node`] in its `Data`, so nothing is allocated. Since frames of `catch_long_jump`
+//! can be skipped by jumps, `long_jump` exits spans of all checkpoints it skips while their frames
+//! are still alive, and the target checkpoint exits its own span on the lander path.
+//!
+//! Jumps from the signal handler of `interrupt` or from foreign code cannot do that. Spans of
+//! checkpoints skipped by them are unlinked by the target, but never exited.
+use core::cell::Cell;
+use core::fmt;
+use core::mem::ManuallyDrop;
+use core::panic::Location;
+use core::ptr;
+
+use tracing::span::EnteredSpan;
+
+use crate::Data;
+
+std::thread_local! {
+    static INNERMOST: Cell<*mut Node> = const { Cell::new(ptr::null_mut()) };
+}
+
+/// The span of a checkpoint, stored in its `Data`.
+pub(crate) struct Node {
+    // It must not make `Data` significant to `Drop`.
+    span: ManuallyDrop<Option<EnteredSpan>>,
+    prev: *mut Node,
+}
+
+impl Node {
+    pub(crate) const fn new() -> Self {
+        Self {
+            span: ManuallyDrop::new(None),
+            prev: ptr::null_mut(),
+        }
+    }
+}
+
+fn node_of(buf: *mut ()) -> *mut Node {
+    buf.wrapping_byte_add(core::mem::offset_of!(Data<()>, trace))
+        .cast()
+}
+
+/// Exits the span of a checkpoint, if not yet, for panics passing through.
+pub(crate) struct Exit(*mut Node);
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        let node = self.0;
+        // SAFETY: The checkpoint is still alive.
+        if let Some(span) = Option::take(unsafe { &mut (*node).span }) {
+            INNERMOST.set(unsafe { (*node).prev });
+            drop(span);
+        }
+    }
+}
+
+/// Enter a span for the checkpoint `buf`. The guard is only built if the span is enabled.
+///
+/// # Safety
+///
+/// `buf` must be a checkpoint which is being entered.
+#[inline]
+pub(crate) unsafe fn enter(buf: *mut ()) -> Option<Exit> {
+    let node = node_of(buf);
+    let prev = INNERMOST.get();
+    // Saved even if disabled, for `land` to unlink inner checkpoints.
+    unsafe { (*node).prev = prev };
+    let span = tracing::debug_span!(target: "sjlj2", "catch_long_jump");
+    if span.is_disabled() {
+        return None;
+    }
+    unsafe { *(*node).span = Some(span.entered()) };
+    INNERMOST.set(node);
+    Some(Exit(node))
+}
+
+/// Exit the span of the checkpoint `buf` after it is jumped to, and unlink inner checkpoints.
+///
+/// # Safety
+///
+/// `buf` must be a checkpoint entered by [`enter`], which is jumped to.
+#[inline]
+pub(crate) unsafe fn land(buf: *mut ()) {
+    let node = node_of(buf);
+    unsafe {
+        INNERMOST.set((*node).prev);
+        drop(Option::take(&mut (*node).span));
+    }
+}
+
+/// Emit an event for a jump to `buf`, inside the span of the innermost checkpoint, then exit
+/// spans of all checkpoints inside `buf`.
+///
+/// # Safety
+///
+/// `buf` must be a live checkpoint, and all checkpoints inside it must be alive.
+#[track_caller]
+pub(crate) unsafe fn jump(buf: *mut (), data: &dyn fmt::Debug) {
+    tracing::debug!(
+        target: "sjlj2",
+        data = ?data,
+        location = %Location::caller(),
+        "long_jump",
+    );
+    // Stacks grow downwards on all supported architectures, thus inner checkpoints are at lower
+    // addresses. `buf` itself may be not linked if its span is disabled.
+    let target = node_of(buf);
+    loop {
+        let node = INNERMOST.get();
+        if node.is_null() || node >= target {
+            break;
+        }
+        unsafe {
+            INNERMOST.set((*node).prev);
+            drop(Option::take(&mut (*node).span));
+        }
+    }
+}
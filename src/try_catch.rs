@@ -0,0 +1,165 @@
+/// Try-catch syntax over [`catch_long_jump`][crate::catch_long_jump].
+///
+/// ```text
+/// sjlj_try! {
+///     |jp| { body }
+///     catch (data) { handler }
+///     finally { cleanup } // Optional.
+/// }
+/// ```
+///
+/// It evaluates to the value of `body` if it returns normally, or the value of `handler` with
+/// `data` bound to the carried value of [`long_jump`][crate::long_jump]. Both must have the
+/// same type. With `catch (a, b)`, it uses [`catch_long_jump_pair`][crate::catch_long_jump_pair]
+/// instead, binding the two words of the payload to `a` and `b`. `data`, `a` and `b` can be
+/// any irrefutable patterns.
+///
+/// `finally` runs after either `body` or `handler` completes, before the value is returned.
+/// It does not run if a panic or a jump to an outer checkpoint passes through.
+///
+/// `body` is the body of a closure, thus `return` and `?` inside it exit the closure, not the
+/// enclosing function.
+///
+/// # Precondition
+///
+/// Same as [`catch_long_jump`][crate::catch_long_jump], the closure must not have a
+/// significant `Drop`.
+///
+/// # Examples
+///
+/// ```
+/// use sjlj2::sjlj_try;
+///
+/// let ret = sjlj_try! {
+///     |jp| {
+///         // SAFETY: There are only POFs between.
+///         unsafe { jp.long_jump(42) }
+///     }
+///     catch (e) { e + 1 }
+/// };
+/// assert_eq!(ret, 43);
+///
+/// // Nested, with a pair payload and cleanup.
+/// let mut log = Vec::new();
+/// let ret = sjlj_try! {
+///     |outer| {
+///         let inner = sjlj_try! {
+///             |_| { 1 }
+///             catch (_) { unreachable!() }
+///         };
+///         unsafe { outer.long_jump((inner, 2)) }
+///     }
+///     catch (a, b) { a + b }
+///     finally { log.push("done") }
+/// };
+/// assert_eq!(ret, 3);
+/// assert_eq!(log, ["done"]);
+/// ```
+///
+/// Malformed input is rejected with a specific error, eg. for a missing `catch`,
+///
+/// ```compile_fail
+/// let ret = sjlj2::sjlj_try! { |_| { 1 } };
+/// ```
+///
+/// a closure header with other than one parameter,
+///
+/// ```compile_fail
+/// let ret = sjlj2::sjlj_try! { |a, b| { 1 } catch (_) { 2 } };
+/// ```
+///
+/// or stray tokens after the handler.
+///
+/// ```compile_fail
+/// let ret = sjlj2::sjlj_try! { |_| { 1 } catch (_) { 2 } 3 };
+/// ```
+#[macro_export]
+macro_rules! sjlj_try {
+    (
+        |$jp:pat_param| $body:block
+        catch ($a:pat_param, $b:pat_param) $handler:block
+        $(finally $finally:block)?
+    ) => {{
+        let ret = match $crate::catch_long_jump_pair(|$jp| $body) {
+            ::core::ops::ControlFlow::Continue(ret) => ret,
+            ::core::ops::ControlFlow::Break(($a, $b)) => $handler,
+        };
+        $($finally)?
+        ret
+    }};
+    (
+        |$jp:pat_param| $body:block
+        catch ($data:pat) $handler:block
+        $(finally $finally:block)?
+    ) => {{
+        let ret = match $crate::catch_long_jump(|$jp| $body) {
+            ::core::ops::ControlFlow::Continue(ret) => ret,
+            ::core::ops::ControlFlow::Break($data) => $handler,
+        };
+        $($finally)?
+        ret
+    }};
+    // Specific errors. They name the offending token where possible, since `compile_error!`
+    // can only point at the whole invocation.
+    (
+        |$jp:pat_param| $body:block
+        catch $params:tt $handler:block
+        finally $finally:block
+        $stray:tt $($rest:tt)*
+    ) => {
+        ::core::compile_error!(::core::concat!(
+            "unexpected `",
+            ::core::stringify!($stray),
+            "` after `finally { .. }` of `sjlj_try!`",
+        ))
+    };
+    (
+        |$jp:pat_param| $body:block
+        catch $params:tt $handler:block
+        finally $($rest:tt)*
+    ) => {
+        ::core::compile_error!("expected a block after `finally` of `sjlj_try!`")
+    };
+    (
+        |$jp:pat_param| $body:block
+        catch $params:tt $handler:block
+        $stray:tt $($rest:tt)*
+    ) => {
+        ::core::compile_error!(::core::concat!(
+            "expected `finally { .. }` or the end of `sjlj_try!`, found `",
+            ::core::stringify!($stray),
+            "`",
+        ))
+    };
+    (|$jp:pat_param| $body:block catch $($rest:tt)*) => {
+        ::core::compile_error!(
+            "expected `catch (data) { .. }` or `catch (a, b) { .. }` in `sjlj_try!`"
+        )
+    };
+    (|$jp:pat_param| $body:block) => {
+        ::core::compile_error!("missing `catch (data) { .. }` after the body of `sjlj_try!`")
+    };
+    (|$jp:pat_param| $body:block $stray:tt $($rest:tt)*) => {
+        ::core::compile_error!(::core::concat!(
+            "expected `catch` after the body of `sjlj_try!`, found `",
+            ::core::stringify!($stray),
+            "`",
+        ))
+    };
+    (|$jp:pat_param| $($rest:tt)*) => {
+        ::core::compile_error!("expected a block as the body of `sjlj_try!`")
+    };
+    (|$jp:pat_param, $($rest:tt)*) => {
+        ::core::compile_error!("expected one parameter in the closure header of `sjlj_try!`")
+    };
+    ($stray:tt $($rest:tt)*) => {
+        ::core::compile_error!(::core::concat!(
+            "expected a closure header `|jp|` at the start of `sjlj_try!`, found `",
+            ::core::stringify!($stray),
+            "`",
+        ))
+    };
+    () => {
+        ::core::compile_error!("expected `sjlj_try! { |jp| { .. } catch (data) { .. } }`")
+    };
+}
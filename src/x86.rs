@@ -1,23 +1,78 @@
-// si, sp, bp, lander
-#[repr(transparent)]
-pub(crate) struct Buf(pub [usize; 4]);
+/// The jump buffer layout on x86.
+///
+/// A jump is performed as follows, with `ecx` pointing to the buffer and `eax` holding the
+/// payload. With feature `fpenv`, `ldmxcsr` (if SSE is enabled) and `fldcw` are executed first
+/// on the respective fields.
+///
+/// ```text
+/// mov esi, [ecx]
+/// mov [ecx], eax
+/// mov esp, [ecx + 4]
+/// mov ebp, [ecx + 8]
+/// jmp dword ptr [ecx + 12]
+/// ```
+#[repr(C)]
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct RawJumpBuf {
+    /// `esi`, and also the payload after jumping.
+    pub esi: usize,
+    /// `esp`.
+    pub sp: usize,
+    /// `ebp`.
+    pub fp: usize,
+    /// The address to jump to.
+    pub lander: usize,
+    /// MXCSR, only with feature `fpenv` and SSE enabled.
+    #[cfg(fpenv)]
+    pub mxcsr: u32,
+    /// x87 FPU control word, only with feature `fpenv`.
+    #[cfg(fpenv)]
+    pub x87_cw: u16,
+}
+
+pub(crate) type Buf = RawJumpBuf;
 
 macro_rules! set_jump_raw {
     ($buf_ptr:expr, $func:path, $lander:block) => {
+        #[cfg(native_unwind)]
+        let jumped: usize;
         core::arch::asm!(
             "call 2f",
             "2:",
             "popl %eax",
+            #[cfg(not(native_unwind))]
             "addl $({lander} - 2b), %eax",
+            #[cfg(native_unwind)]
+            "addl $(3f - 2b), %eax",
             "movl %esi,   (%ecx)",
             "movl %esp,  4(%ecx)",
             "movl %ebp,  8(%ecx)",
             "movl %eax, 12(%ecx)",
+            #[cfg(all(fpenv, target_feature = "sse"))]
+            "stmxcsr 16(%ecx)",
+            #[cfg(fpenv)]
+            "fnstcw 20(%ecx)",
             "call {func}",
+            #[cfg(native_unwind)]
+            "xorl %eax, %eax",
+            #[cfg(native_unwind)]
+            "jmp 4f",
+            #[cfg(native_unwind)]
+            "3:",
+            #[cfg(native_unwind)]
+            "movl $1, %eax",
+            #[cfg(native_unwind)]
+            "4:",
 
             in("cx") $buf_ptr, // arg0 for fastcall
             func = sym $func,
+            #[cfg(not(native_unwind))]
             lander = label $lander,
+            #[cfg(native_unwind)]
+            lateout("ax") jumped,
+            #[cfg(native_unwind)]
+            options(may_unwind),
             // Workaround: <https://github.com/rust-lang/rust/issues/74558>
             options(att_syntax),
 
@@ -29,7 +84,10 @@ macro_rules! set_jump_raw {
             lateout("di") _,
             // Caller saved registers.
             clobber_abi("fastcall"),
-        )
+        );
+        #[cfg(native_unwind)]
+        #[allow(unused_unsafe)]
+        if jumped != 0 $lander
     };
 }
 
@@ -42,6 +100,10 @@ pub(crate) unsafe fn long_jump_raw(buf: *mut (), data: usize) -> ! {
             #[cfg(emit_cfi)]
             ".cfi_undefined eip",
 
+            #[cfg(all(fpenv, target_feature = "sse"))]
+            "ldmxcsr dword ptr [ecx + 16]",
+            #[cfg(fpenv)]
+            "fldcw word ptr [ecx + 20]",
             "mov esi, [ecx]",
             "mov [ecx], eax",
             "mov esp, [ecx + 4]",
@@ -57,3 +119,35 @@ pub(crate) unsafe fn long_jump_raw(buf: *mut (), data: usize) -> ! {
         )
     }
 }
+
+/// Same as `long_jump_raw`, but store two words of payload.
+#[inline]
+pub(crate) unsafe fn long_jump_raw_pair(buf: *mut (), data: (usize, usize)) -> ! {
+    unsafe {
+        core::arch::asm!(
+            #[cfg(emit_cfi)]
+            ".cfi_remember_state",
+            #[cfg(emit_cfi)]
+            ".cfi_undefined eip",
+
+            #[cfg(all(fpenv, target_feature = "sse"))]
+            "ldmxcsr dword ptr [ecx + 16]",
+            #[cfg(fpenv)]
+            "fldcw word ptr [ecx + 20]",
+            "mov esi, [ecx]",
+            "mov [ecx], eax",
+            "mov esp, [ecx + 4]",
+            "mov ebp, [ecx + 8]",
+            "mov [ecx + 4], edx",
+            "jmp dword ptr [ecx + 12]",
+
+            #[cfg(emit_cfi)]
+            ".cfi_restore_state",
+
+            in("cx") buf,
+            in("ax") data.0,
+            in("dx") data.1,
+            options(noreturn, nostack),
+        )
+    }
+}
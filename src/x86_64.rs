@@ -1,44 +1,123 @@
-// rbx, rsp, rbp, lander
-#[repr(transparent)]
-pub(crate) struct Buf(pub [usize; 4]);
+/// The jump buffer layout on x86\_64.
+///
+/// A jump is performed as follows, with `rcx` pointing to the buffer and `rax` holding the
+/// payload. With feature `fpenv`, `ldmxcsr` and `fldcw` are executed first on the respective
+/// fields.
+///
+/// ```text
+/// mov rbx, [rcx     ]
+/// mov rsp, [rcx +  8]
+/// mov rbp, [rcx + 16]
+/// mov [rcx], rax
+/// jmp qword ptr [rcx + 24]
+/// ```
+///
+/// With feature `frame-walk`, `rbx` and `rbp` are not saved, and jumping by the sequence above
+/// is not supported.
+#[repr(C)]
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct RawJumpBuf {
+    /// `rbx`, and also the payload after jumping.
+    pub rbx: usize,
+    /// `rsp`.
+    pub sp: usize,
+    /// `rbp`.
+    pub fp: usize,
+    /// The address to jump to.
+    pub lander: usize,
+    /// MXCSR, only with feature `fpenv`.
+    #[cfg(fpenv)]
+    pub mxcsr: u32,
+    /// x87 FPU control word, only with feature `fpenv`.
+    #[cfg(fpenv)]
+    pub x87_cw: u16,
+}
+
+pub(crate) type Buf = RawJumpBuf;
 
 macro_rules! set_jump_raw {
     ($buf_ptr:expr, $func:path, $lander:block) => {
+        #[cfg(native_unwind)]
+        let jumped: usize;
         core::arch::asm!(
+            #[cfg(not(native_unwind))]
             "lea rax, [rip + {lander}]",
+            #[cfg(native_unwind)]
+            "lea rax, [rip + 3f]",
+            #[cfg(not(feature = "frame-walk"))]
             "mov [rdi     ], rbx",
             "mov [rdi +  8], rsp",
+            #[cfg(not(feature = "frame-walk"))]
             "mov [rdi + 16], rbp",
             "mov [rdi + 24], rax",
+            #[cfg(fpenv)]
+            "stmxcsr dword ptr [rdi + 32]",
+            #[cfg(fpenv)]
+            "fnstcw word ptr [rdi + 36]",
             "call {func}",
+            #[cfg(native_unwind)]
+            "xor eax, eax",
+            #[cfg(native_unwind)]
+            "jmp 4f",
+            #[cfg(native_unwind)]
+            "3:",
+            #[cfg(native_unwind)]
+            "mov eax, 1",
+            #[cfg(native_unwind)]
+            "4:",
 
             in("rdi") $buf_ptr, // arg0
             func = sym $func,
+            #[cfg(not(native_unwind))]
             lander = label $lander,
+            #[cfg(native_unwind)]
+            lateout("ax") jumped,
+            // Labels are not allowed with `may_unwind`. The lander is selected by `jumped` instead.
+            #[cfg(native_unwind)]
+            options(may_unwind),
 
-            // Clobber more default callee saved registers.
+            // Clobber more default callee saved registers, unless they are recovered by
+            // walking frames on jumping.
             // lateout("bx") _, // LLVM reserved.
             // lateout("sp") _, // sp
             // lateout("bp") _, // LLVM reserved.
+            #[cfg(not(feature = "frame-walk"))]
             lateout("r12") _,
+            #[cfg(not(feature = "frame-walk"))]
             lateout("r13") _,
+            #[cfg(not(feature = "frame-walk"))]
             lateout("r14") _,
+            #[cfg(not(feature = "frame-walk"))]
             lateout("r15") _,
 
             // Default caller saved registers.
             clobber_abi("sysv64"),
-        )
+        );
+        #[cfg(native_unwind)]
+        #[allow(unused_unsafe)]
+        if jumped != 0 $lander
     };
 }
 
 #[inline]
 pub(crate) unsafe fn long_jump_raw(jp: *mut (), data: usize) -> ! {
+    #[cfg(feature = "frame-walk")]
+    unsafe {
+        crate::frame_walk::long_jump_raw(jp.cast(), (data, 0))
+    }
+
+    #[cfg(not(feature = "frame-walk"))]
     unsafe {
         core::arch::asm!(
             #[cfg(emit_cfi)]
             ".cfi_remember_state",
             #[cfg(emit_cfi)]
             ".cfi_undefined rip",
+            #[cfg(fpenv)]
+            "ldmxcsr dword ptr [rcx + 32]",
+            #[cfg(fpenv)]
+            "fldcw word ptr [rcx + 36]",
             "mov rbx, [rcx     ]",
             "mov rsp, [rcx +  8]",
             "mov rbp, [rcx + 16]",
@@ -53,3 +132,39 @@ pub(crate) unsafe fn long_jump_raw(jp: *mut (), data: usize) -> ! {
         )
     }
 }
+
+/// Same as `long_jump_raw`, but store two words of payload.
+#[inline]
+pub(crate) unsafe fn long_jump_raw_pair(jp: *mut (), data: (usize, usize)) -> ! {
+    #[cfg(feature = "frame-walk")]
+    unsafe {
+        crate::frame_walk::long_jump_raw(jp.cast(), data)
+    }
+
+    #[cfg(not(feature = "frame-walk"))]
+    unsafe {
+        core::arch::asm!(
+            #[cfg(emit_cfi)]
+            ".cfi_remember_state",
+            #[cfg(emit_cfi)]
+            ".cfi_undefined rip",
+            #[cfg(fpenv)]
+            "ldmxcsr dword ptr [rcx + 32]",
+            #[cfg(fpenv)]
+            "fldcw word ptr [rcx + 36]",
+            "mov rbx, [rcx     ]",
+            "mov rsp, [rcx +  8]",
+            "mov rbp, [rcx + 16]",
+            "mov [rcx], rax",
+            "mov [rcx + 8], rdx",
+            "jmp qword ptr [rcx + 24]",
+            #[cfg(emit_cfi)]
+            ".cfi_restore_state",
+
+            in("cx") jp,
+            in("ax") data.0,
+            in("dx") data.1,
+            options(noreturn, nostack),
+        )
+    }
+}
@@ -3,9 +3,44 @@
 //! Otherwise, `long_jmp` from `catch_unwind` in the ordinary path will leave
 //! the SEH chain un-restored, causing any later exception segfaults.
 
-// si, sp, bp, lander, SEH head
-#[repr(transparent)]
-pub(crate) struct Buf(pub [usize; 5]);
+/// The jump buffer layout on x86 MSVC targets.
+///
+/// A jump is performed as follows, with `ecx` pointing to the buffer and `eax` holding the
+/// payload. With feature `fpenv`, `ldmxcsr` (if SSE is enabled) and `fldcw` are executed first
+/// on the respective fields.
+///
+/// ```text
+/// mov esi, [ecx]
+/// mov [ecx], eax
+/// mov esp, [ecx + 4]
+/// mov ebp, [ecx + 8]
+/// mov eax, [ecx + 16]
+/// mov fs:[0], eax
+/// jmp dword ptr [ecx + 12]
+/// ```
+#[repr(C)]
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct RawJumpBuf {
+    /// `esi`, and also the payload after jumping.
+    pub esi: usize,
+    /// `esp`.
+    pub sp: usize,
+    /// `ebp`.
+    pub fp: usize,
+    /// The address to jump to.
+    pub lander: usize,
+    /// The head of the SEH chain, `fs:[0]`.
+    pub seh_head: usize,
+    /// MXCSR, only with feature `fpenv` and SSE enabled.
+    #[cfg(fpenv)]
+    pub mxcsr: u32,
+    /// x87 FPU control word, only with feature `fpenv`.
+    #[cfg(fpenv)]
+    pub x87_cw: u16,
+}
+
+pub(crate) type Buf = RawJumpBuf;
 
 macro_rules! set_jump_raw {
     ($buf_ptr:expr, $func:path, $lander:block) => {
@@ -20,6 +55,10 @@ macro_rules! set_jump_raw {
             "movl %eax, 12(%ecx)",
             "movl %fs:0, %eax",
             "movl %eax, 16(%ecx)",
+            #[cfg(all(fpenv, target_feature = "sse"))]
+            "stmxcsr 20(%ecx)",
+            #[cfg(fpenv)]
+            "fnstcw 24(%ecx)",
             "call {func}",
 
             in("cx") $buf_ptr, // arg0 for fastcall
@@ -49,6 +88,10 @@ pub(crate) unsafe fn long_jump_raw(buf: *mut (), data: usize) -> ! {
             #[cfg(emit_cfi)]
             ".cfi_undefined eip",
 
+            #[cfg(all(fpenv, target_feature = "sse"))]
+            "ldmxcsr dword ptr [ecx + 20]",
+            #[cfg(fpenv)]
+            "fldcw word ptr [ecx + 24]",
             "mov esi, [ecx]",
             "mov [ecx], eax",
             "mov esp, [ecx + 4]",
@@ -66,3 +109,37 @@ pub(crate) unsafe fn long_jump_raw(buf: *mut (), data: usize) -> ! {
         )
     }
 }
+
+/// Same as `long_jump_raw`, but store two words of payload.
+#[inline]
+pub(crate) unsafe fn long_jump_raw_pair(buf: *mut (), data: (usize, usize)) -> ! {
+    unsafe {
+        core::arch::asm!(
+            #[cfg(emit_cfi)]
+            ".cfi_remember_state",
+            #[cfg(emit_cfi)]
+            ".cfi_undefined eip",
+
+            #[cfg(all(fpenv, target_feature = "sse"))]
+            "ldmxcsr dword ptr [ecx + 20]",
+            #[cfg(fpenv)]
+            "fldcw word ptr [ecx + 24]",
+            "mov esi, [ecx]",
+            "mov [ecx], eax",
+            "mov esp, [ecx + 4]",
+            "mov ebp, [ecx + 8]",
+            "mov [ecx + 4], edx",
+            "mov eax, [ecx + 16]",
+            "mov fs:[0], eax",
+            "jmp dword ptr [ecx + 12]",
+
+            #[cfg(emit_cfi)]
+            ".cfi_restore_state",
+
+            in("cx") buf,
+            in("ax") data.0,
+            in("dx") data.1,
+            options(noreturn, nostack),
+        )
+    }
+}
@@ -6,7 +6,7 @@ use std::time::{Duration, Instant};
 
 use sjlj2::catch_long_jump;
 
-const TEST_DURATION: Duration = Duration::from_secs(60);
+const TEST_DURATION: Duration = Duration::from_mins(1);
 const CHUNK: usize = 1_000_000;
 
 #[test]
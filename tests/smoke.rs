@@ -4,7 +4,7 @@ use std::ops::ControlFlow;
 use std::panic::{catch_unwind, panic_any};
 use std::ptr::read_volatile;
 
-use sjlj2::catch_long_jump;
+use sjlj2::{catch_long_jump, catch_long_jump_pair};
 
 #[test]
 fn smoke() {
@@ -23,6 +23,338 @@ fn smoke() {
     assert_eq!(ret, ControlFlow::Break(0));
 }
 
+#[test]
+fn pair_payload() {
+    let ret = catch_long_jump_pair(|_| 42i32);
+    assert_eq!(ret, ControlFlow::Continue(42));
+
+    let ret = catch_long_jump_pair(|jp| unsafe { jp.long_jump((13, usize::MAX)) });
+    assert_eq!(ret, ControlFlow::Break((13, usize::MAX)));
+
+    // Nested with a single-word checkpoint.
+    let ret = catch_long_jump_pair(|outer| {
+        let inner = catch_long_jump(|jp| unsafe { jp.long_jump(black_box(1)) });
+        assert_eq!(inner, ControlFlow::Break(1));
+        unsafe { outer.long_jump((black_box(2), black_box(3))) }
+    });
+    assert_eq!(
+        ret,
+        ControlFlow::Break::<(usize, usize), Infallible>((2, 3))
+    );
+}
+
+#[test]
+fn try_catch() {
+    use sjlj2::sjlj_try;
+
+    #[inline(never)]
+    fn check(jp: sjlj2::JumpPoint<'_>, x: usize) -> usize {
+        if x > 10 {
+            unsafe { jp.long_jump(x) }
+        }
+        x
+    }
+
+    let mut finished = 0;
+    for (x, expect) in [(1, 2), (20, 21)] {
+        let ret = sjlj_try! {
+            |jp| { check(jp, x) * 2 }
+            catch (e) { e + 1 }
+            finally { finished += 1 }
+        };
+        assert_eq!(ret, expect);
+    }
+    assert_eq!(finished, 2);
+
+    // The inner `finally` is skipped by the outer jump.
+    let ret = sjlj_try! {
+        |outer| {
+            sjlj_try! {
+                |_inner| { unsafe { outer.long_jump((3, 4)) } }
+                catch (_) { unreachable!() }
+                finally { finished += 10 }
+            }
+        }
+        catch (a, b) { a * b }
+    };
+    assert_eq!(ret, 12);
+    assert_eq!(finished, 2);
+}
+
+#[cfg(all(feature = "nightly", feature = "std"))]
+#[test]
+fn throws() {
+    use sjlj2::{Throws, catch_long_jump_any, catch_throws, throw_any};
+
+    #[derive(Debug, PartialEq)]
+    struct Error(u8);
+
+    impl From<u8> for Error {
+        fn from(x: u8) -> Self {
+            Self(x)
+        }
+    }
+
+    #[inline(never)]
+    fn leaf(x: u8) -> Throws<u8, Error> {
+        let x = if x == 0 { Err(0u8) } else { Ok(x) };
+        Throws::new(x?)
+    }
+
+    #[inline(never)]
+    fn chain(xs: &[u8]) -> Throws<u32, Error> {
+        let mut acc = 0;
+        for &x in xs {
+            acc += u32::from(leaf(x)?);
+        }
+        Throws::new(acc)
+    }
+
+    #[inline(never)]
+    fn other() -> Throws<u8, i32> {
+        Throws::new(Err::<u8, i32>(42)?)
+    }
+
+    assert_eq!(unsafe { catch_throws(|| chain(&[1, 2, 3])) }, Ok(6));
+    assert_eq!(unsafe { catch_throws(|| chain(&[1, 0, 3])) }, Err(Error(0)));
+
+    // Nested scopes catch their own errors.
+    let ret = unsafe {
+        catch_throws(|| {
+            let inner = catch_throws(|| chain(&[0]));
+            assert_eq!(inner, Err(Error(0)));
+            chain(&[4, 0])
+        })
+    };
+    assert_eq!(ret, Err(Error(0)));
+
+    // Errors of other types are resumed as panics.
+    let ret = catch_unwind(|| unsafe {
+        catch_throws::<u8, Error, _>(|| Throws::new(other().into_inner()))
+    });
+    assert_eq!(*ret.unwrap_err().downcast::<i32>().unwrap(), 42);
+
+    // `?` never jumps to scopes of `catch_long_jump_any`.
+    let ret = catch_unwind(|| catch_long_jump_any(|| chain(&[0])));
+    assert_eq!(*ret.unwrap_err().downcast::<Error>().unwrap(), Error(0));
+    let ret = unsafe {
+        catch_throws::<_, Error, _>(|| {
+            Throws::new(catch_long_jump_any(|| -> () { throw_any(1u8) }).is_err())
+        })
+    };
+    assert_eq!(ret, Ok(true));
+}
+
+#[cfg(feature = "macros")]
+#[test]
+fn pof_macro() {
+    use sjlj2::{JumpPoint, pof};
+
+    struct Limit(usize);
+
+    impl Limit {
+        #[pof]
+        fn check(&self, jp: JumpPoint<'_>, x: usize) -> usize {
+            if x > self.0 {
+                unsafe { jp.long_jump(x) }
+            }
+            x
+        }
+    }
+
+    #[pof]
+    fn sum<T: Copy + Into<usize>>(jp: JumpPoint<'_>, limit: &Limit, xs: &[T]) -> usize {
+        let mut acc = 0;
+        for &x in xs {
+            acc += limit.check(jp, x.into());
+        }
+        let (lo, hi): (usize, usize);
+        (lo, hi) = (0, acc);
+        lo + Limit::check(limit, jp, hi)
+    }
+
+    let limit = Limit(10);
+    let ret = catch_long_jump(|jp| sum(jp, &limit, &[1u8, 2, 3]));
+    assert_eq!(ret, ControlFlow::Continue(6));
+    let ret = catch_long_jump(|jp| sum(jp, &limit, &[1u8, 20, 3]));
+    assert_eq!(ret, ControlFlow::Break(20));
+    let ret = catch_long_jump(|jp| sum(jp, &limit, &[5u8, 6]));
+    assert_eq!(ret, ControlFlow::Break(11));
+}
+
+#[cfg(feature = "diagnostics")]
+#[test]
+fn diagnostics() {
+    use sjlj2::catch_long_jump_traced;
+
+    let ret = catch_long_jump_traced(|_| 42);
+    assert!(matches!(ret, ControlFlow::Continue(42)));
+
+    let line = line!();
+    let ret = catch_long_jump_traced(|jp| -> () { unsafe { jp.long_jump(13) } });
+    let ControlFlow::Break(info) = ret else {
+        unreachable!()
+    };
+    assert_eq!(info.data(), 13);
+    let loc = info.location().unwrap();
+    assert_eq!((loc.file(), loc.line()), (file!(), line + 1));
+
+    // Untraced checkpoints inside do not interfere.
+    let ret = catch_long_jump_traced(|outer| -> () {
+        let inner = catch_long_jump(|jp| unsafe { jp.long_jump(1) });
+        assert_eq!(inner, ControlFlow::<_, ()>::Break(1));
+        unsafe { sjlj2::long_jump(outer, 2) }
+    });
+    let ControlFlow::Break(info) = ret else {
+        unreachable!()
+    };
+    assert_eq!(info.data(), 2);
+    assert_eq!(info.location().unwrap().line(), line + 13);
+}
+
+#[cfg(feature = "tracing")]
+mod recorder {
+    use std::fmt::{self, Write};
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata};
+
+    pub struct Recorder {
+        next_id: AtomicU64,
+        pub log: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        pub const fn new() -> Self {
+            Self {
+                next_id: AtomicU64::new(0),
+                log: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            write!(self.0, " {}={value:?}", field.name()).unwrap();
+        }
+    }
+
+    impl tracing::Subscriber for &'static Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, _: &Attributes<'_>) -> Id {
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(String::new());
+            event.record(&mut fields);
+            self.log.lock().unwrap().push(format!("event{}", fields.0));
+        }
+        fn enter(&self, id: &Id) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("enter {}", id.into_u64()));
+        }
+        fn exit(&self, id: &Id) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("exit {}", id.into_u64()));
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing() {
+    use recorder::Recorder;
+
+    static RECORDER: Recorder = Recorder::new();
+    let recorder = &RECORDER;
+    let line = line!();
+    tracing::subscriber::with_default(recorder, || {
+        let ret = catch_long_jump(|outer| {
+            let _ = catch_long_jump(|_inner| unsafe { outer.long_jump(7) });
+            unreachable!();
+        });
+        assert_eq!(ret, ControlFlow::<_, ()>::Break(7));
+    });
+    let location = format!("{}:{}:61", file!(), line + 3);
+    assert_eq!(
+        *recorder.log.lock().unwrap(),
+        [
+            "enter 1".into(),
+            "enter 2".into(),
+            format!("event message=long_jump data=7 location={location}"),
+            "exit 2".into(),
+            "exit 1".into(),
+        ],
+    );
+
+    // A panic passing through also exits the span.
+    #[cfg(any(feature = "unwind", all(feature = "nightly", not(windows))))]
+    {
+        recorder.log.lock().unwrap().clear();
+        tracing::subscriber::with_default(recorder, || {
+            let ret = catch_unwind(|| {
+                let _ = catch_long_jump(|_jp| panic_any(42usize));
+            });
+            assert!(ret.is_err());
+            let _ = catch_long_jump(|_jp| {});
+        });
+        assert_eq!(
+            *recorder.log.lock().unwrap(),
+            ["enter 3", "exit 3", "enter 4", "exit 4"],
+        );
+    }
+}
+
+// Wrappers of `long_jump` report their callers.
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_caller_location() {
+    use recorder::Recorder;
+
+    static RECORDER: Recorder = Recorder::new();
+    let recorder = &RECORDER;
+    let token = sjlj2::CancelToken::new();
+    token.cancel();
+    let line = line!();
+    tracing::subscriber::with_default(recorder, || {
+        let _ = sjlj2::catch_cancellable(&token, |scope| unsafe { scope.check() });
+        let _ = sjlj2::with_jump_scope(|| unsafe { sjlj2::throw(1) });
+        let _ = sjlj2::catch_long_jump_any(|| unsafe { sjlj2::throw_any(()) });
+    });
+    let locations = recorder
+        .log
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|log| {
+            Some(
+                log.split_once(" location=")?
+                    .1
+                    .rsplit_once(':')?
+                    .0
+                    .to_owned(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        locations,
+        [1, 2, 3].map(|i| format!("{}:{}", file!(), line + i + 1)),
+    );
+}
+
 #[test]
 fn must_jump() {
     // This should eliminate the normal return path.
@@ -32,7 +364,7 @@ fn must_jump() {
 
 #[test]
 // WAIT: <https://github.com/rust-lang/rust/issues/152079>
-#[expect(unused_assignments)]
+#[allow(unused_assignments)]
 fn issue_2625() {
     #[inline(never)]
     fn foo() -> (usize, usize) {
@@ -59,7 +391,7 @@ fn issue_2625() {
     assert_eq!(foo(), (13, 2));
 }
 
-#[cfg(feature = "unwind")]
+#[cfg(any(feature = "unwind", all(feature = "nightly", not(windows))))]
 #[test]
 fn resume_panic() {
     let ret = catch_unwind(|| {
@@ -69,6 +401,594 @@ fn resume_panic() {
     assert_eq!(payload, 42usize);
 }
 
+#[test]
+fn catch_with_hook() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use sjlj2::{CatchUnwind, catch_long_jump_with};
+
+    static CAUGHT: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counting;
+
+    impl CatchUnwind for Counting {
+        type Payload = Box<dyn std::any::Any + Send>;
+
+        fn catch_unwind<R, F: FnOnce() -> R>(f: F) -> Result<R, Self::Payload> {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        }
+
+        fn resume_unwind(payload: Self::Payload) -> ! {
+            CAUGHT.fetch_add(1, Ordering::Relaxed);
+            std::panic::resume_unwind(payload)
+        }
+    }
+
+    let ret = catch_long_jump_with::<Counting, _, _>(|jp| unsafe { jp.long_jump(42) });
+    assert_eq!(ret, ControlFlow::<_, ()>::Break(42));
+
+    let ret = catch_unwind(|| {
+        let _ = catch_long_jump_with::<Counting, (), _>(|_jp| panic_any(13usize));
+    });
+    assert_eq!(*ret.unwrap_err().downcast::<usize>().unwrap(), 13);
+    // The portable implementation passes through panics by itself.
+    if cfg!(not(miri)) {
+        assert_eq!(CAUGHT.load(Ordering::Relaxed), 1);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn outcome() {
+    use sjlj2::{Outcome, catch_long_jump_outcome};
+
+    let ret = catch_long_jump_outcome(|jp| -> () { unsafe { jp.long_jump(13) } });
+    assert!(matches!(ret, Outcome::Jumped(13)));
+
+    let ret = catch_long_jump_outcome(|_jp| -> () { panic_any(42usize) });
+    let Outcome::Panicked(payload) = ret else {
+        panic!("{ret:?}")
+    };
+    assert_eq!(*payload.downcast::<usize>().unwrap(), 42);
+
+    // Jumping to an outer checkpoint passes through.
+    let ret = catch_long_jump(|jp| {
+        let _ = catch_long_jump_outcome(|_| -> () { unsafe { jp.long_jump(7) } });
+        unreachable!();
+    });
+    assert_eq!(ret, ControlFlow::<_, ()>::Break(7));
+}
+
+#[cfg(feature = "forced-unwind")]
+#[test]
+fn forced_unwind() {
+    use std::cell::Cell;
+
+    struct Guard<'a>(&'a Cell<u32>);
+    impl Drop for Guard<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[inline(never)]
+    fn deep(n: u32, dropped: &Cell<u32>, jp: sjlj2::JumpPoint<'_>) -> u32 {
+        let _guard = Guard(dropped);
+        if n == 0 {
+            unsafe { jp.long_jump_unwinding(42) }
+        }
+        deep(n - 1, dropped, jp) + 1
+    }
+
+    let dropped = Cell::new(0);
+    let ret = catch_long_jump(|jp| {
+        let _guard = Guard(&dropped);
+        deep(3, &dropped, jp)
+    });
+    assert_eq!(ret, ControlFlow::Break(42));
+    assert_eq!(dropped.get(), 5);
+
+    // Still usable after forced unwinding.
+    let ret = catch_long_jump(|jp| unsafe { jp.long_jump_unwinding(13) });
+    assert_eq!(ret, ControlFlow::Break(13));
+}
+
+// Values live across `catch_long_jump` are kept in callee-saved registers, which the skipped
+// frames clobber. This matters for feature `frame-walk`, which recovers them on jump.
+#[test]
+fn callee_saved() {
+    #[inline(never)]
+    fn deep(n: usize, jp: sjlj2::JumpPoint<'_>, unwinding: bool) -> usize {
+        let [x0, x1, x2, x3, x4, x5] = black_box([n; 6]);
+        if n == 0 {
+            #[cfg(feature = "forced-unwind")]
+            if unwinding {
+                unsafe { jp.long_jump_unwinding(x0 ^ x1 ^ x2 ^ x3 ^ x4 ^ x5) }
+            }
+            let _ = unwinding;
+            unsafe { jp.long_jump(x0 ^ x1 ^ x2 ^ x3 ^ x4 ^ x5) }
+        }
+        let ret = deep(n - 1, jp, unwinding);
+        ret + x0 + x1 + x2 + x3 + x4 + x5
+    }
+
+    #[inline(never)]
+    fn run(unwinding: bool) -> [usize; 6] {
+        let [x0, x1, x2, x3, x4, x5] = black_box([1, 2, 3, 4, 5, 6]);
+        let ret = catch_long_jump(|jp| deep(black_box(5), jp, unwinding));
+        assert_eq!(ret, ControlFlow::Break(0));
+        [x0, x1, x2, x3, x4, x5]
+    }
+
+    assert_eq!(run(false), [1, 2, 3, 4, 5, 6]);
+    if cfg!(feature = "forced-unwind") {
+        assert_eq!(run(true), [1, 2, 3, 4, 5, 6]);
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn jump_scope() {
+    use sjlj2::{throw, with_jump_scope};
+
+    #[inline(never)]
+    fn builtin(x: usize) -> usize {
+        if x != 0 {
+            unsafe { throw(x) }
+        }
+        x
+    }
+
+    assert_eq!(with_jump_scope(|| builtin(0)), ControlFlow::Continue(0));
+    assert_eq!(with_jump_scope(|| builtin(1)), ControlFlow::Break(1));
+
+    // Jumping to a scope pops it, and the next throw goes to the outer one.
+    let ret = with_jump_scope(|| {
+        let inner = with_jump_scope(|| {
+            let innermost = with_jump_scope(|| builtin(2));
+            assert_eq!(innermost, ControlFlow::Break(2));
+            builtin(3)
+        });
+        assert_eq!(inner, ControlFlow::Break(3));
+        assert_eq!(with_jump_scope(|| builtin(0)), ControlFlow::Continue(0));
+        builtin(4)
+    });
+    assert_eq!(ret, ControlFlow::Break(4));
+
+    // All scopes are popped.
+    let ret = catch_unwind(|| unsafe { throw(5) });
+    assert!(ret.is_err());
+}
+
+// Scopes skipped by a jump to an outer checkpoint other than a scope are popped too.
+#[cfg(feature = "std")]
+#[test]
+fn jump_scope_skipped() {
+    use sjlj2::{
+        CancelToken, Cancelled, catch_cancellable, catch_long_jump_any, throw, with_jump_point,
+        with_jump_scope,
+    };
+
+    let token = CancelToken::new();
+    token.cancel();
+    let ret = catch_cancellable(&token, |scope| with_jump_scope(|| unsafe { scope.check() }));
+    assert_eq!(ret, Err(Cancelled));
+    assert!(with_jump_point(|jp| jp.is_none()));
+    let ret = catch_unwind(|| unsafe { throw(5) });
+    assert!(ret.is_err());
+
+    let ret = catch_long_jump(|jp| {
+        let _ = catch_long_jump_any(|| -> () { unsafe { jp.long_jump(1) } });
+    });
+    assert_eq!(ret, ControlFlow::Break(1));
+    let ret = catch_unwind(|| catch_long_jump_any(|| unsafe { sjlj2::throw_any(2u8) }));
+    assert_eq!(*ret.unwrap().unwrap_err().downcast::<u8>().unwrap(), 2);
+
+    // Scopes outside the checkpoint are kept.
+    let ret = with_jump_scope(|| {
+        let _ = catch_long_jump(|jp| with_jump_scope(|| unsafe { jp.long_jump(3) }));
+        unsafe { throw(4) }
+    });
+    assert_eq!(ret, ControlFlow::<_, ()>::Break(4));
+}
+
+#[cfg(feature = "unwind")]
+#[test]
+fn jump_scope_panic() {
+    use sjlj2::{throw, with_jump_scope};
+
+    let ret = with_jump_scope(|| {
+        let ret = catch_unwind(|| with_jump_scope(|| panic_any(42usize)));
+        assert_eq!(*ret.unwrap_err().downcast::<usize>().unwrap(), 42usize);
+        // The panicked scope is popped.
+        unsafe { throw(13) }
+    });
+    assert_eq!(ret, ControlFlow::<_, ()>::Break(13));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn catch_any() {
+    use sjlj2::{catch_long_jump_any, throw_any};
+
+    let ret = catch_long_jump_any(|| 42);
+    assert_eq!(ret.unwrap(), 42);
+
+    let ret = catch_long_jump_any(|| {
+        let inner = catch_long_jump_any(|| -> () { unsafe { throw_any(1i32) } });
+        assert_eq!(*inner.unwrap_err().downcast::<i32>().unwrap(), 1);
+        unsafe { throw_any(String::from("outer")) }
+    });
+    let payload: Result<(), _> = ret;
+    assert_eq!(*payload.unwrap_err().downcast::<String>().unwrap(), "outer");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn jump_future() {
+    use sjlj2::{JumpFuture, JumpPoint, throw, with_jump_point, with_jump_scope};
+    use std::cell::Cell;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    struct Yield(bool);
+    impl Future for Yield {
+        type Output = ();
+        fn poll(mut self: std::pin::Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+            if std::mem::replace(&mut self.0, true) {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    struct Guard<'a>(&'a Cell<bool>);
+    impl Drop for Guard<'_> {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    #[inline(never)]
+    fn plugin(x: usize) -> usize {
+        if x != 0 {
+            unsafe { throw(x) }
+        }
+        x
+    }
+
+    let mut cx = Context::from_waker(Waker::noop());
+
+    let mut fut = pin!(JumpFuture::new(async {
+        Yield(false).await;
+        plugin(0)
+    }));
+    assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+    assert_eq!(
+        fut.as_mut().poll(&mut cx),
+        Poll::Ready(ControlFlow::Continue(0))
+    );
+
+    // The inner future holding a `Drop` across `Pending` is dropped on jump.
+    let dropped = Cell::new(false);
+    let mut fut = pin!(JumpFuture::new(async {
+        let _guard = Guard(&dropped);
+        Yield(false).await;
+        plugin(7)
+    }));
+    assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+    // The scope does not survive across `Pending`, so this goes to the outer one.
+    assert_eq!(with_jump_scope(|| plugin(1)), ControlFlow::Break(1));
+    assert_eq!(
+        fut.as_mut().poll(&mut cx),
+        Poll::Ready(ControlFlow::Break(7))
+    );
+    assert!(dropped.get());
+
+    // The jump point of the scope is reachable during `poll` only.
+    let mut fut = pin!(JumpFuture::new(async {
+        assert!(with_jump_point(|jp| jp.is_some()));
+        Yield(false).await;
+        with_jump_point(|jp| unsafe { jp.unwrap().long_jump(3) });
+    }));
+    assert_eq!(fut.as_mut().poll(&mut cx), Poll::Pending);
+    assert!(with_jump_point(|jp| jp.is_none()));
+    assert_eq!(
+        fut.as_mut().poll(&mut cx),
+        Poll::Ready(ControlFlow::<_, ()>::Break(3))
+    );
+
+    // A `poll` exited through a checkpoint other than its scope does not leave the scope behind.
+    let target = Cell::new(std::ptr::null_mut());
+    let mut fut = pin!(JumpFuture::new(async {
+        unsafe { JumpPoint::<usize>::from_raw(target.get()).long_jump(5) };
+    }));
+    let ret = catch_long_jump(|jp| {
+        target.set(jp.as_raw());
+        let _ = fut.as_mut().poll(&mut cx);
+    });
+    assert_eq!(ret, ControlFlow::Break(5));
+    assert!(with_jump_point(|jp| jp.is_none()));
+}
+
+#[cfg(feature = "unwind")]
+#[test]
+fn throw_any_without_scope() {
+    let ret = catch_unwind(|| unsafe { sjlj2::throw_any(5u8) });
+    assert_eq!(*ret.unwrap_err().downcast::<u8>().unwrap(), 5);
+}
+
+#[test]
+fn cancel() {
+    use sjlj2::{CancelToken, Cancelled, catch_cancellable};
+
+    #[inline(never)]
+    fn work(scope: &sjlj2::CancelScope<'_>, n: usize) -> usize {
+        for i in 0..n {
+            unsafe { scope.check() };
+            black_box(i);
+        }
+        n
+    }
+
+    let token = CancelToken::new();
+    assert_eq!(catch_cancellable(&token, |scope| work(scope, 10)), Ok(10));
+
+    token.cancel();
+    assert_eq!(catch_cancellable(&token, |scope| work(scope, 0)), Ok(0));
+    assert_eq!(
+        catch_cancellable(&token, |scope| work(scope, 10)),
+        Err(Cancelled)
+    );
+    assert!(token.is_cancelled());
+
+    // Nested scopes with different tokens.
+    let outer = CancelToken::new();
+    let ret = catch_cancellable(&outer, |outer_scope| {
+        let inner = CancelToken::new();
+        let ret = catch_cancellable(&inner, |scope| {
+            outer_scope.token().cancel();
+            work(scope, 10)
+        });
+        assert_eq!(ret, Ok(10));
+        work(outer_scope, 10)
+    });
+    assert_eq!(ret, Err(Cancelled));
+
+    token.reset();
+    let ret = std::thread::scope(|s| {
+        catch_cancellable(&token, |scope| {
+            let token = scope.token();
+            s.spawn(move || token.cancel());
+            work(scope, usize::MAX)
+        })
+    });
+    assert_eq!(ret, Err(Cancelled));
+}
+
+#[cfg(feature = "interrupt")]
+#[test]
+fn interrupt() {
+    use sjlj2::{Interrupted, catch_interruptible};
+
+    fn spin() -> ! {
+        loop {
+            black_box(());
+        }
+    }
+
+    let mut outer_handle = None;
+    let mut skipped_handle = None;
+    let ret = unsafe {
+        catch_interruptible(|outer| {
+            outer_handle = Some(outer.clone());
+            let inner = catch_interruptible(|inner| {
+                let inner = inner.clone();
+                std::thread::spawn(move || assert!(inner.interrupt()));
+                spin()
+            });
+            assert_eq!(inner, Err(Interrupted));
+
+            // Interrupting the outer scope passes through the running inner one, which
+            // still completes.
+            let outer = outer.clone();
+            std::thread::spawn(move || assert!(outer.interrupt()));
+            let _ = catch_interruptible(|inner| {
+                skipped_handle = Some(inner.clone());
+                spin()
+            });
+            unreachable!();
+        })
+    };
+    assert_eq!(ret, Err::<(), _>(Interrupted));
+
+    let outer = outer_handle.unwrap();
+    assert!(outer.is_finished());
+    assert!(!outer.interrupt());
+    let inner = skipped_handle.unwrap();
+    assert!(inner.is_finished());
+    assert!(!inner.interrupt());
+
+    // Returns normally and pending requests are ignored.
+    let ret = unsafe { catch_interruptible(Clone::clone) };
+    let handle = ret.unwrap();
+    assert!(!handle.interrupt());
+    let ret = unsafe { catch_interruptible(|_| 42) };
+    assert_eq!(ret, Ok(42));
+}
+
+#[cfg(feature = "interrupt")]
+#[test]
+fn timeout() {
+    use std::time::{Duration, Instant};
+
+    use sjlj2::{TimedOut, catch_long_jump_with_timeout};
+
+    fn spin() {
+        loop {
+            black_box(());
+        }
+    }
+
+    let ret = unsafe { catch_long_jump_with_timeout(Duration::from_secs(10), || 42) };
+    assert_eq!(ret, Ok(42));
+
+    let inst = Instant::now();
+    let ret = unsafe { catch_long_jump_with_timeout(Duration::from_millis(50), spin) };
+    assert_eq!(ret, Err(TimedOut));
+    assert!(inst.elapsed() >= Duration::from_millis(50));
+
+    // The inner timeout fires first, then the outer one is still armed.
+    let ret = unsafe {
+        catch_long_jump_with_timeout(Duration::from_millis(100), || {
+            let inner = catch_long_jump_with_timeout(Duration::from_millis(10), spin);
+            assert_eq!(inner, Err(TimedOut));
+            spin();
+        })
+    };
+    assert_eq!(ret, Err(TimedOut));
+
+    // The outer timeout fires first and passes through the inner one.
+    let ret = unsafe {
+        catch_long_jump_with_timeout(Duration::from_millis(10), || {
+            let _ = catch_long_jump_with_timeout(Duration::from_secs(10), spin);
+            unreachable!();
+        })
+    };
+    assert_eq!(ret, Err::<(), _>(TimedOut));
+}
+
+#[cfg(all(
+    feature = "fpenv",
+    not(miri),
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+))]
+#[test]
+fn fpenv() {
+    use std::arch::asm;
+
+    #[cfg(target_arch = "x86_64")]
+    const ROUND_TOWARD_ZERO: usize = 0b11 << 13;
+    #[cfg(target_arch = "aarch64")]
+    const ROUND_TOWARD_ZERO: usize = 0b11 << 22;
+
+    fn get() -> usize {
+        let mut ret = 0usize;
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            asm!("stmxcsr dword ptr [{}]", in(reg) &raw mut ret);
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            asm!("mrs {}, fpcr", out(reg) ret);
+        }
+        ret
+    }
+
+    fn set(v: usize) {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            asm!("ldmxcsr dword ptr [{}]", in(reg) &raw const v);
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            asm!("msr fpcr, {}", in(reg) v);
+        }
+    }
+
+    let orig = get();
+    assert_eq!(orig & ROUND_TOWARD_ZERO, 0);
+    let ret = catch_long_jump(|jp| {
+        set(orig | ROUND_TOWARD_ZERO);
+        assert_eq!(get(), orig | ROUND_TOWARD_ZERO);
+        unsafe { jp.long_jump(1) }
+    });
+    assert_eq!(ret, ControlFlow::Break(1));
+    assert_eq!(get(), orig);
+}
+
+// Emulate a jump from JIT-compiled code.
+#[cfg(all(
+    not(miri),
+    not(feature = "frame-walk"),
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+#[test]
+fn raw_jump_buf() {
+    use std::arch::asm;
+    use std::mem::offset_of;
+
+    use sjlj2::RawJumpBuf;
+
+    #[inline(never)]
+    unsafe fn jit_throw(buf: *mut (), payload: usize) -> ! {
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            asm!(
+                "mov rbx, [rcx + {rbx}]",
+                "mov rsp, [rcx + {sp}]",
+                "mov rbp, [rcx + {fp}]",
+                "mov [rcx + {payload}], rax",
+                "jmp qword ptr [rcx + {lander}]",
+                rbx = const offset_of!(RawJumpBuf, rbx),
+                sp = const RawJumpBuf::SP_OFFSET,
+                fp = const RawJumpBuf::FP_OFFSET,
+                payload = const RawJumpBuf::PAYLOAD_OFFSET,
+                lander = const RawJumpBuf::LANDER_OFFSET,
+                in("rcx") buf,
+                in("rax") payload,
+                options(noreturn, nostack),
+            )
+        }
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            asm!(
+                "ldr x2, [x1, #{sp}]",
+                "ldr x19, [x1, #{x19}]",
+                "ldr fp, [x1, #{fp}]",
+                "ldr lr, [x1, #{lander}]",
+                "mov sp, x2",
+                "str x0, [x1, #{payload}]",
+                "ret",
+                x19 = const offset_of!(RawJumpBuf, x19),
+                sp = const RawJumpBuf::SP_OFFSET,
+                fp = const RawJumpBuf::FP_OFFSET,
+                payload = const RawJumpBuf::PAYLOAD_OFFSET,
+                lander = const RawJumpBuf::LANDER_OFFSET,
+                in("x0") payload,
+                in("x1") buf,
+                options(noreturn, nostack),
+            )
+        }
+    }
+
+    assert_eq!(RawJumpBuf::SIZE, size_of::<RawJumpBuf>());
+    assert!(RawJumpBuf::ALIGN.is_power_of_two());
+
+    let ret = catch_long_jump(|jp| {
+        let buf = jp.as_raw();
+        assert_eq!(buf as usize % RawJumpBuf::ALIGN, 0);
+        unsafe { jit_throw(buf, 42) }
+    });
+    assert_eq!(ret, ControlFlow::Break(42));
+}
+
+// Miri must report a jump to a checkpoint whose closure already returned, as Undefined Behavior
+// which aborts the test binary. Thus it is ignored and run separately, expecting the failure.
+#[cfg(miri)]
+#[test]
+#[ignore = "expected to be rejected by Miri"]
+fn stale_jump_point() {
+    let mut stale = None;
+    let ret = catch_long_jump(|jp| {
+        stale = Some(jp.as_raw());
+    });
+    assert_eq!(ret, ControlFlow::Continue(()));
+    let jp = unsafe { sjlj2::JumpPoint::<'_>::from_raw(stale.unwrap()) };
+    let _ = catch_unwind(|| unsafe { jp.long_jump(1) });
+}
+
 // Test DWARF state or SEH chain restoration.
 #[test]
 fn after_panic() {
@@ -80,6 +1000,33 @@ fn after_panic() {
     assert_eq!(payload, 13usize);
 }
 
+// Values only live on the lander path are dead inside the closure. If it were inlined into the
+// frame of the checkpoint, their registers and stack slots could be reused there.
+#[test]
+fn lander_only_live() {
+    #[inline(never)]
+    fn run() -> u64 {
+        let a: [u64; 16] = std::array::from_fn(|i| black_box(i as u64 + 100));
+        let ret = catch_long_jump(|jp| {
+            let b: [u64; 16] = std::array::from_fn(|i| black_box(i as u64 * 3));
+            let mut acc = [0u64; 16];
+            for _ in 0..black_box(3) {
+                for (i, x) in acc.iter_mut().enumerate() {
+                    *x = x.wrapping_mul(31).wrapping_add(b[(i + 5) % 16]);
+                }
+            }
+            black_box(acc);
+            unsafe { jp.long_jump(1) }
+        });
+        match ret {
+            ControlFlow::Break(_) => a.iter().sum(),
+            ControlFlow::Continue(()) => 0,
+        }
+    }
+
+    assert_eq!(run(), (100..116).sum());
+}
+
 // <https://github.com/rust-lang/libc/issues/1596>
 #[test]
 fn libc_issue_1596() {